@@ -0,0 +1,2 @@
+ALTER TABLE dispatcher_processes
+    DROP COLUMN failure_reason;
@@ -0,0 +1,2 @@
+ALTER TABLE dispatcher_processes
+    ADD COLUMN failure_reason VARCHAR(255) NULL AFTER mode;
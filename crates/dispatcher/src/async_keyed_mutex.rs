@@ -42,6 +42,19 @@ where
         }
     }
 
+    /// Number of keys whose mutex is still referenced; dead entries awaiting
+    /// `cleanup` are not counted.
+    pub fn len(&self) -> usize {
+        self.map
+            .iter()
+            .filter(|entry| entry.value().strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn cleanup(&self) {
         let dead_keys: Vec<K> = self
             .map
@@ -54,3 +67,100 @@ where
         }
     }
 }
+
+impl<K> AsyncKeyedMutex<K, tokio::sync::Mutex<()>>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Locks the key only if its mutex is free right now, without waiting.
+    /// The owned guard keeps the mutex alive, so `cleanup` leaves the key in place.
+    pub fn try_lock(&self, key: K) -> Option<tokio::sync::OwnedMutexGuard<()>> {
+        self.get_mutex(key).try_lock_owned().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_guard_holders_of_one_key_are_mutually_exclusive() {
+        let locks = Arc::new(AsyncKeyedMutex::<u32>::new());
+        let holders = Arc::new(AtomicU32::new(0));
+        let max_holders = Arc::new(AtomicU32::new(0));
+
+        let tasks: Vec<_> = (0..2)
+            .map(|_| {
+                let locks = locks.clone();
+                let holders = holders.clone();
+                let max_holders = max_holders.clone();
+                tokio::spawn(async move {
+                    for _ in 0..10 {
+                        let source_lock = locks.get_mutex(7);
+                        let _source_guard = source_lock.lock().await;
+                        let current = holders.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_holders.fetch_max(current, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(1)).await;
+                        holders.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(max_holders.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_keeps_locked_keys() {
+        let locks = AsyncKeyedMutex::<u32>::new();
+
+        let source_lock = locks.get_mutex(7);
+        let source_guard = source_lock.lock().await;
+        locks.cleanup();
+        assert_eq!(locks.map.len(), 1);
+        assert_eq!(locks.len(), 1);
+        //a second caller must wait on the same mutex, not get a fresh one
+        assert!(locks.get_mutex(7).try_lock().is_err());
+
+        drop(source_guard);
+        drop(source_lock);
+        assert_eq!(locks.len(), 0);
+        locks.cleanup();
+        assert_eq!(locks.map.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_try_lock_fails_while_locked() {
+        let locks = AsyncKeyedMutex::<u32>::new();
+
+        let source_guard = locks.try_lock(7).unwrap();
+        assert!(locks.try_lock(7).is_none());
+        assert!(locks.try_lock(8).is_some());
+
+        drop(source_guard);
+        assert!(locks.try_lock(7).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_len_counts_only_live_keys_after_cleanup() {
+        let locks = AsyncKeyedMutex::<u32>::new();
+
+        let source_guard = locks.try_lock(7).unwrap();
+        drop(locks.try_lock(8).unwrap());
+        locks.cleanup();
+
+        assert_eq!(locks.len(), 1);
+        assert_eq!(locks.map.len(), 1);
+        assert!(locks.try_lock(7).is_none());
+
+        drop(source_guard);
+        locks.cleanup();
+        assert!(locks.is_empty());
+        assert_eq!(locks.map.len(), 0);
+    }
+}
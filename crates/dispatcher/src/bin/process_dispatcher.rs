@@ -1,11 +1,12 @@
+use process_dispatcher::cancellation_ext::sleep_unless_cancelled;
+use process_dispatcher::db_repository::DbRepository;
 use process_dispatcher::dispatcher::Dispatcher;
 use process_dispatcher::http_server::start_http_server;
 use std::sync::Arc;
 use tokio::signal;
 use tokio::signal::unix::SignalKind;
-use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 #[tokio::main]
 async fn main() {
@@ -13,29 +14,59 @@ async fn main() {
 
     //init env variables
     let env_params = process_dispatcher::env::fetch_env_params();
+    process_dispatcher::cancellation_ext::set_cancellation_log_level(
+        env_params.cancellation_log_level(),
+    );
 
     //prepare a mechanism for shutdown event processing
     let cancellation_token = prepare_cancellation_token_on_posix_signal();
 
-    let dispatcher = Dispatcher::new(&env_params).await.unwrap();
+    let db_repository = DbRepository::new(&env_params).await.unwrap();
+    let dispatcher = Dispatcher::new(&env_params, Arc::new(db_repository));
     let arc_dispatcher = Arc::new(dispatcher);
 
+    #[cfg(feature = "statsd")]
+    let statsd_client = init_statsd(&env_params, &arc_dispatcher);
+    #[cfg(feature = "statsd")]
+    if let Some(statsd_client) = &statsd_client {
+        statsd_client
+            .clone()
+            .start_queue_sampling(arc_dispatcher.clone(), cancellation_token.clone());
+    }
+    #[cfg(not(feature = "statsd"))]
+    if env_params.statsd_addr().is_some() {
+        warn!("STATSD_ADDR is set, but the binary is built without the statsd feature");
+    }
+
     //use cleaning of the lock mechanism for source ids
     arc_dispatcher.clone().start_clean_source_locks();
 
+    if env_params.max_process_age_secs().is_some() {
+        arc_dispatcher
+            .clone()
+            .start_expire_old_processes(cancellation_token.clone());
+    }
+
+    arc_dispatcher
+        .clone()
+        .start_reap_stale_processes(cancellation_token.clone());
+
     //prepare continuous scheduling of processes
     let dispatcher_arc_clone = arc_dispatcher.clone();
     let cancellation_token_clone = cancellation_token.clone();
     tokio::task::spawn(async move {
         loop {
+            #[cfg(feature = "statsd")]
+            let cycle_started_at = std::time::Instant::now();
             match dispatcher_arc_clone
-                .prepare_schedule(&cancellation_token_clone)
+                .prepare_schedule_with_retry(&cancellation_token_clone)
                 .await
             {
-                Ok(created_cnt) => {
-                    info!("Cycle completed successfully");
-                    if created_cnt == 0 {
-                        sleep(std::time::Duration::from_secs(60)).await;
+                Ok(report) => {
+                    info!("Cycle completed successfully: {:?}", report);
+                    #[cfg(feature = "statsd")]
+                    if let Some(statsd_client) = &statsd_client {
+                        statsd_client.schedule_cycle(&report, cycle_started_at.elapsed());
                     }
                 }
                 Err(process_dispatcher::dispatcher::DispatcherError::TerminatingSignalReceived) => {
@@ -44,19 +75,65 @@ async fn main() {
                 }
                 Err(e) => error!("Error: {:?}", e),
             }
+            let interval = dispatcher_arc_clone.schedule_interval();
+            debug!("main:schedule_thread: sleeping {:?} until the next cycle", interval);
+            if !sleep_unless_cancelled(&cancellation_token_clone, interval).await {
+                info!("main:schedule_thread: Schedule loop cancelled");
+                break;
+            }
         }
     });
 
-    start_http_server(
+    if let Err(e) = start_http_server(
         env_params.http_port(),
         arc_dispatcher.clone(),
+        env_params
+            .http_request_timeout_secs()
+            .map(std::time::Duration::from_secs),
+        env_params.admin_api_key().map(str::to_string),
+        env_params.max_concurrent_claims(),
         &cancellation_token,
     )
-    .await;
+    .await
+    {
+        //a dispatcher that schedules but cannot be reached is worse than a crash
+        error!(
+            "Failed to start HTTP server on port {}: {}",
+            env_params.http_port(),
+            e
+        );
+        cancellation_token.cancel();
+        std::process::exit(1);
+    }
 
+    let cancelled_cnt = process_dispatcher::cancellation_ext::cancelled_operations_count();
+    if cancelled_cnt > 0 {
+        info!("{} operations cancelled during shutdown", cancelled_cnt);
+    }
     info!("Application shutdown completed");
 }
 
+#[cfg(feature = "statsd")]
+fn init_statsd(
+    env_params: &process_dispatcher::env::EnvParams,
+    dispatcher: &Dispatcher,
+) -> Option<Arc<process_dispatcher::statsd::StatsdClient>> {
+    let addr = env_params.statsd_addr()?;
+    match process_dispatcher::statsd::StatsdClient::connect(addr) {
+        Ok(client) => {
+            info!("Sending StatsD metrics to {}", addr);
+            let client = Arc::new(client);
+            dispatcher.post_commit_hooks().register(client.clone());
+            Some(client)
+        }
+        Err(e) => {
+            //metrics are best-effort, the dispatcher keeps running without them
+            error!("Failed to set up StatsD client for {}: {}", addr, e);
+            None
+        }
+    }
+}
+
 fn prepare_cancellation_token_on_posix_signal() -> CancellationToken {
     let cancellation_token = CancellationToken::new();
 
@@ -89,9 +166,19 @@ fn prepare_cancellation_token_on_posix_signal() -> CancellationToken {
 }
 
 fn init_tracing() {
-    use tracing_subscriber::EnvFilter;
+    use process_dispatcher::dispatcher::lifecycle::{self, LIFECYCLE_TARGET};
+    use tracing_subscriber::filter::filter_fn;
+    use tracing_subscriber::prelude::*;
+    use tracing_subscriber::{fmt, EnvFilter};
 
     let filter = EnvFilter::try_from_env("RUST_LOG").unwrap_or_else(|_| EnvFilter::new("trace"));
 
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+    //lifecycle events go to stdout as plain JSON lines, everything else as text
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_filter(filter_fn(|metadata| {
+            metadata.target() != LIFECYCLE_TARGET
+        })))
+        .with(lifecycle::json_layer(std::io::stdout))
+        .init();
 }
@@ -25,7 +25,7 @@
 //! This crate provides a simple extension trait that eliminates this boilerplate:
 //!
 //! ```rust
-//! use tokio_cancellation_ext::{CancellationExt, CancellationError};
+//! use process_dispatcher::cancellation_ext::{CancellationExt, CancellationError};
 //! use tokio_util::sync::CancellationToken;
 //!
 //! #[derive(Debug)]
@@ -47,7 +47,7 @@
 //!
 //! // Clean, readable code without boilerplate
 //! let result = fetch_data()
-//!     .with_cancellation(&token, "fetch_data")
+//!     .with_cancellation::<MyError>(&token, "fetch_data")
 //!     .await?;
 //! # Ok(())
 //! # }
@@ -67,7 +67,7 @@
 //! ### Basic Usage
 //!
 //! ```rust
-//! use tokio_cancellation_ext::{CancellationExt, CancellationError};
+//! use process_dispatcher::cancellation_ext::{CancellationExt, CancellationError};
 //! use tokio_util::sync::CancellationToken;
 //!
 //! #[derive(Debug)]
@@ -91,11 +91,11 @@
 //!
 //! // Multiple cancellable operations
 //! let response = network_call()
-//!     .with_cancellation(&token, "network_call")
+//!     .with_cancellation::<AppError>(&token, "network_call")
 //!     .await?;
 //!
 //! let data = database_query()
-//!     .with_cancellation(&token, "database_query")
+//!     .with_cancellation::<AppError>(&token, "database_query")
 //!     .await?;
 //! # Ok(())
 //! # }
@@ -106,7 +106,7 @@
 //! The trait works with any error type that implements `From<CancellationError>`:
 //!
 //! ```rust
-//! use tokio_cancellation_ext::{CancellationExt, CancellationError};
+//! use process_dispatcher::cancellation_ext::{CancellationExt, CancellationError};
 //! use tokio_util::sync::CancellationToken;
 //!
 //! // Works with anyhow
@@ -127,10 +127,74 @@
 //! # }
 //! ```
 
-use tracing::info;
+use tracing::{debug, info, warn};
 use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::time::{Duration, Instant};
 use tokio_util::sync::CancellationToken;
 
+/// Level of the per-operation "cancellation signal received" line.
+///
+/// Every wrapped future logs it once cancelled, so during shutdown there is one
+/// line per in-flight operation; `debug` / `off` hide them, while
+/// [`cancelled_operations_count`] still allows a single aggregated line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancellationLogLevel {
+    Info,
+    Debug,
+    Off,
+}
+
+impl FromStr for CancellationLogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "info" => Ok(CancellationLogLevel::Info),
+            "debug" => Ok(CancellationLogLevel::Debug),
+            "off" => Ok(CancellationLogLevel::Off),
+            other => Err(format!("unknown cancellation log level '{}'", other)),
+        }
+    }
+}
+
+static CANCELLATION_LOG_LEVEL: AtomicU8 = AtomicU8::new(CancellationLogLevel::Info as u8);
+static CANCELLED_OPERATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the process-wide level of per-operation cancellation logs.
+pub fn set_cancellation_log_level(level: CancellationLogLevel) {
+    CANCELLATION_LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Number of operations interrupted by a cancellation since start.
+pub fn cancelled_operations_count() -> u64 {
+    CANCELLED_OPERATIONS.load(Ordering::Relaxed)
+}
+
+/// Sleeps for `duration` unless the token is cancelled first, e.g. between two
+/// cycles of a loop. Returns `false` if cancelled (also when it already was).
+pub async fn sleep_unless_cancelled(token: &CancellationToken, duration: Duration) -> bool {
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => false,
+        _ = tokio::time::sleep(duration) => true,
+    }
+}
+
+fn log_cancellation(context: &str) {
+    CANCELLED_OPERATIONS.fetch_add(1, Ordering::Relaxed);
+    match CANCELLATION_LOG_LEVEL.load(Ordering::Relaxed) {
+        level if level == CancellationLogLevel::Info as u8 => {
+            info!("{}: cancellation signal received", context)
+        }
+        level if level == CancellationLogLevel::Debug as u8 => {
+            debug!("{}: cancellation signal received", context)
+        }
+        _ => {}
+    }
+}
+
 /// Extension trait for adding cancellation support to any Future
 ///
 /// This trait allows you to easily add cancellation support to any future that returns
@@ -148,7 +212,7 @@ use tokio_util::sync::CancellationToken;
 /// ## Basic Usage
 ///
 /// ```rust
-/// use tokio_cancellation_ext::{CancellationExt, CancellationError};
+/// use process_dispatcher::cancellation_ext::{CancellationExt, CancellationError};
 /// use tokio_util::sync::CancellationToken;
 ///
 /// #[derive(Debug)]
@@ -188,7 +252,7 @@ use tokio_util::sync::CancellationToken;
 /// ## Chaining Multiple Operations
 ///
 /// ```rust
-/// use tokio_cancellation_ext::{CancellationExt, CancellationError};
+/// use process_dispatcher::cancellation_ext::{CancellationExt, CancellationError};
 /// use tokio_util::sync::CancellationToken;
 ///
 /// #[derive(Debug)]
@@ -213,11 +277,11 @@ use tokio_util::sync::CancellationToken;
 ///
 /// // Both operations can be cancelled
 /// let user = fetch_user(user_id)
-///     .with_cancellation(&token, "fetch_user")
+///     .with_cancellation::<AppError>(&token, "fetch_user")
 ///     .await?;
 ///
 /// update_cache(&user)
-///     .with_cancellation(&token, "update_cache")
+///     .with_cancellation::<AppError>(&token, "update_cache")
 ///     .await?;
 /// # Ok(())
 /// # }
@@ -256,7 +320,7 @@ pub trait CancellationExt<T> {
     /// # Examples
     ///
     /// ```rust
-    /// use tokio_cancellation_ext::{CancellationExt, CancellationError};
+    /// use process_dispatcher::cancellation_ext::{CancellationExt, CancellationError};
     /// use tokio_util::sync::CancellationToken;
     ///
     /// #[derive(Debug)]
@@ -285,7 +349,7 @@ pub trait CancellationExt<T> {
     /// let token = CancellationToken::new();
     ///
     /// let rows = execute_query()
-    ///     .with_cancellation(&token, "execute_query")
+    ///     .with_cancellation::<DatabaseError>(&token, "execute_query")
     ///     .await?;
     ///
     /// println!("Got {} rows", rows.len());
@@ -301,6 +365,116 @@ pub trait CancellationExt<T> {
         CancellationError: Into<E>,
         Self::OriginalError: Into<E>,
         Self: 'a;
+
+    /// Adds cancellation support and an upper bound on the run time to a future
+    ///
+    /// Like [`CancellationExt::with_cancellation`], but the future is also dropped
+    /// once `timeout` elapses, e.g. so a wedged query cannot block a scheduling
+    /// cycle forever. The context and the elapsed time are logged on timeout.
+    ///
+    /// # Returns
+    ///
+    /// Returns a new future that will either:
+    /// - Complete with `Ok(T)` if the original future completes in time
+    /// - Complete with `Err(E)` if the original future returns an error (converted via `Into`)
+    /// - Complete with `Err(E)` if cancellation is requested (from `CancellationError`)
+    /// - Complete with `Err(E)` if `timeout` elapses first (from `TimeoutError`)
+    ///
+    /// Cancellation wins when it is requested and the timeout has elapsed as well.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use process_dispatcher::cancellation_ext::{
+    ///     CancellationError, CancellationExt, TimeoutError,
+    /// };
+    /// use std::time::Duration;
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// #[derive(Debug)]
+    /// enum QueryError {
+    ///     Cancelled,
+    ///     TimedOut,
+    /// }
+    ///
+    /// impl From<CancellationError> for QueryError {
+    ///     fn from(_: CancellationError) -> Self {
+    ///         QueryError::Cancelled
+    ///     }
+    /// }
+    ///
+    /// impl From<TimeoutError> for QueryError {
+    ///     fn from(_: TimeoutError) -> Self {
+    ///         QueryError::TimedOut
+    ///     }
+    /// }
+    ///
+    /// # async fn execute_query() -> Result<u64, QueryError> { Ok(1) }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), QueryError> {
+    /// let token = CancellationToken::new();
+    ///
+    /// let timeout = Duration::from_secs(30);
+    /// let rows = execute_query()
+    ///     .with_cancellation_timeout::<QueryError>(&token, "execute_query", timeout)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn with_cancellation_timeout<'a, E>(
+        self,
+        token: &'a CancellationToken,
+        context: &'a str,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<T, E>> + Send + 'a
+    where
+        CancellationError: Into<E>,
+        TimeoutError: Into<E>,
+        Self::OriginalError: Into<E>,
+        Self: 'a;
+
+    /// Adds cancellation support to a future, treating cancellation as "no result"
+    ///
+    /// Unlike [`CancellationExt::with_cancellation`], the target error type does not
+    /// need a `From<CancellationError>` impl: a cancelled operation is reported as
+    /// `Ok(None)`, which suits callers that have nothing to do on shutdown anyway.
+    ///
+    /// # Returns
+    ///
+    /// Returns a new future that will either:
+    /// - Complete with `Ok(Some(T))` if the original future completes successfully
+    /// - Complete with `Err(E)` if the original future returns an error (converted via `Into`)
+    /// - Complete with `Ok(None)` if cancellation is requested
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use process_dispatcher::cancellation_ext::CancellationExt;
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// # async fn poll_queue() -> Result<u32, std::io::Error> { Ok(3) }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), std::io::Error> {
+    /// let token = CancellationToken::new();
+    ///
+    /// match poll_queue()
+    ///     .with_cancellation_opt::<std::io::Error>(&token, "poll_queue")
+    ///     .await?
+    /// {
+    ///     Some(cnt) => println!("{} queued", cnt),
+    ///     None => println!("shutting down"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn with_cancellation_opt<'a, E>(
+        self,
+        token: &'a CancellationToken,
+        context: &'a str,
+    ) -> impl Future<Output = Result<Option<T>, E>> + Send + 'a
+    where
+        Self::OriginalError: Into<E>,
+        Self: 'a;
 }
 
 /// Error type representing a cancellation request
@@ -314,7 +488,7 @@ pub trait CancellationExt<T> {
 /// ## Converting to Custom Error Types
 ///
 /// ```rust
-/// use tokio_cancellation_ext::CancellationError;
+/// use process_dispatcher::cancellation_ext::CancellationError;
 ///
 /// #[derive(Debug)]
 /// enum MyError {
@@ -338,7 +512,7 @@ pub trait CancellationExt<T> {
 /// ## Using with anyhow
 ///
 /// ```rust
-/// use tokio_cancellation_ext::CancellationError;
+/// use process_dispatcher::cancellation_ext::CancellationError;
 /// # #[cfg(feature = "anyhow")]
 /// use anyhow::Result;
 ///
@@ -360,6 +534,22 @@ impl std::fmt::Display for CancellationError {
 
 impl std::error::Error for CancellationError {}
 
+/// Error type representing an operation that did not finish in time
+///
+/// Returned by [`CancellationExt::with_cancellation_timeout`] once its timeout
+/// elapses. Like `CancellationError`, callers convert it into their own error
+/// type via `From`, which keeps a timeout distinguishable from a cancellation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError;
+
+impl std::fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Operation timed out")
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
 impl<F, T, OriginalError> CancellationExt<T> for F
 where
     F: Future<Output = Result<T, OriginalError>> + Send,
@@ -376,11 +566,11 @@ where
         OriginalError: Into<E>,
         F: 'a,
     {
+        let context_owned = context.to_string();
         async move {
-            let context_owned = context.to_string();
             tokio::select! {
                 _ = token.cancelled() => {
-                    info!("{}: cancellation signal received", context_owned);
+                    log_cancellation(&context_owned);
                     Err(CancellationError.into())
                 }
                 result = self => {
@@ -389,6 +579,61 @@ where
             }
         }
     }
+
+    fn with_cancellation_timeout<'a, E>(
+        self,
+        token: &'a CancellationToken,
+        context: &'a str,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<T, E>> + Send + 'a
+    where
+        CancellationError: Into<E>,
+        TimeoutError: Into<E>,
+        OriginalError: Into<E>,
+        F: 'a,
+    {
+        let context_owned = context.to_string();
+        async move {
+            let started_at = Instant::now();
+            tokio::select! {
+                biased;
+                _ = token.cancelled() => {
+                    log_cancellation(&context_owned);
+                    Err(CancellationError.into())
+                }
+                result = self => {
+                    result.map_err(Into::into)
+                }
+                _ = tokio::time::sleep(timeout) => {
+                    warn!("{}: timed out after {:?}", context_owned, started_at.elapsed());
+                    Err(TimeoutError.into())
+                }
+            }
+        }
+    }
+
+    fn with_cancellation_opt<'a, E>(
+        self,
+        token: &'a CancellationToken,
+        context: &'a str,
+    ) -> impl Future<Output = Result<Option<T>, E>> + Send + 'a
+    where
+        OriginalError: Into<E>,
+        F: 'a,
+    {
+        let context_owned = context.to_string();
+        async move {
+            tokio::select! {
+                _ = token.cancelled() => {
+                    log_cancellation(&context_owned);
+                    Ok(None)
+                }
+                result = self => {
+                    result.map(Some).map_err(Into::into)
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -399,9 +644,16 @@ mod tests {
     #[derive(Debug, PartialEq)]
     enum TestError {
         Cancelled,
+        TimedOut,
         Custom(String),
     }
 
+    impl From<TimeoutError> for TestError {
+        fn from(_: TimeoutError) -> Self {
+            TestError::TimedOut
+        }
+    }
+
     impl From<CancellationError> for TestError {
         fn from(_: CancellationError) -> Self {
             TestError::Cancelled
@@ -447,12 +699,132 @@ mod tests {
         assert_eq!(result.unwrap_err(), TestError::Cancelled);
     }
 
+    #[tokio::test]
+    async fn test_cancellations_are_counted() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let before = cancelled_operations_count();
+
+        let result: Result<(), TestError> = std::future::pending::<Result<(), std::io::Error>>()
+            .with_cancellation(&token, "test_counted")
+            .await;
+
+        assert_eq!(result.unwrap_err(), TestError::Cancelled);
+        assert!(cancelled_operations_count() > before);
+    }
+
+    #[test]
+    fn test_cancellation_log_level_from_str() {
+        assert_eq!("debug".parse(), Ok(CancellationLogLevel::Debug));
+        assert_eq!("INFO".parse(), Ok(CancellationLogLevel::Info));
+        assert_eq!("off".parse(), Ok(CancellationLogLevel::Off));
+        assert!("warn".parse::<CancellationLogLevel>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sleep_is_interrupted_by_cancellation() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            canceller.cancel();
+        });
+
+        let slept = tokio::time::timeout(
+            Duration::from_secs(5),
+            sleep_unless_cancelled(&token, Duration::from_secs(60)),
+        )
+        .await
+        .expect("the sleep must end on cancellation, not after the full interval");
+
+        assert!(!slept);
+        assert!(!sleep_unless_cancelled(&token, Duration::ZERO).await);
+    }
+
+    #[tokio::test]
+    async fn test_sleep_without_cancellation_completes() {
+        let token = CancellationToken::new();
+
+        assert!(sleep_unless_cancelled(&token, Duration::from_millis(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_completes_before_timeout() {
+        let token = CancellationToken::new();
+
+        let result: Result<u32, TestError> = async { Ok::<_, std::io::Error>(7) }
+            .with_cancellation_timeout(&token, "test_in_time", Duration::from_secs(5))
+            .await;
+
+        assert_eq!(result.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_times_out() {
+        let token = CancellationToken::new();
+
+        let result: Result<(), TestError> = std::future::pending::<Result<(), std::io::Error>>()
+            .with_cancellation_timeout(&token, "test_timeout", Duration::from_millis(10))
+            .await;
+
+        assert_eq!(result.unwrap_err(), TestError::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_wins_over_timeout() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let result: Result<(), TestError> = std::future::pending::<Result<(), std::io::Error>>()
+            .with_cancellation_timeout(&token, "test_cancelled", Duration::ZERO)
+            .await;
+
+        assert_eq!(result.unwrap_err(), TestError::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_opt_yields_the_value() {
+        let token = CancellationToken::new();
+
+        let result: Result<Option<u32>, std::io::Error> = async { Ok::<_, std::io::Error>(7) }
+            .with_cancellation_opt(&token, "test_opt_value")
+            .await;
+
+        assert_eq!(result.unwrap(), Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_opt_yields_none_on_cancellation() {
+        let token = CancellationToken::new();
+        token.cancel();
+
+        //`std::io::Error` has no `From<CancellationError>`
+        let result: Result<Option<()>, std::io::Error> =
+            std::future::pending::<Result<(), std::io::Error>>()
+                .with_cancellation_opt(&token, "test_opt_cancelled")
+                .await;
+
+        assert!(result.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_opt_propagates_the_original_error() {
+        let token = CancellationToken::new();
+
+        let result: Result<Option<()>, std::io::Error> =
+            async { Err::<(), _>(std::io::Error::other("test error")) }
+                .with_cancellation_opt(&token, "test_opt_error")
+                .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "test error");
+    }
+
     #[tokio::test]
     async fn test_original_error_propagation() {
         let token = CancellationToken::new();
 
         async fn failing_operation() -> Result<String, std::io::Error> {
-            Err(std::io::Error::new(std::io::ErrorKind::Other, "test error"))
+            Err(std::io::Error::other("test error"))
         }
 
         let result: Result<String, TestError> =
@@ -1,46 +1,430 @@
 use crate::env::EnvParams;
+use crate::process_class::ProcessClasses;
+use crate::process_store::{
+    CandidateRow, ProcessFilter, ProcessRecord, ProcessStore, RecordStream,
+    SOURCE_IDS_CHUNK_SIZE,
+};
+use async_trait::async_trait;
 use shared::{DispatchState, ProcessingMode};
-use futures::Stream;
+use futures::{future, StreamExt};
 use sqlx::types::Uuid;
-use sqlx::{mysql::MySqlPoolOptions, MySqlPool};
+use sqlx::migrate::{Migrate, Migrator};
+use sqlx::mysql::{MySqlConnectOptions, MySqlPoolOptions, MySqlRow};
+use sqlx::{FromRow, MySql, MySqlPool, QueryBuilder, Row};
+use std::collections::HashSet;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// `db/migrations/` embedded at compile time, applied with `AUTO_MIGRATE=true`.
+static PD_MIGRATOR: Migrator = sqlx::migrate!("./db/migrations");
+
+/// Session timezone forced on every connection. `created_at`/`updated_at` are
+/// `TIMESTAMP` columns, which MySQL renders in the session timezone, while the
+/// dispatcher parses them as UTC (`DispatchTimeFormatter::db_to_dt`).
+const DB_SESSION_TIMEZONE: &str = "+00:00";
+
+/// Workaround for sqlx treating VARCHAR columns as VARBINARY under utf8mb4_bin collation.
+fn get_string(row: &MySqlRow, column: &str) -> Result<String, sqlx::Error> {
+    let bytes: Option<Vec<u8>> = row.try_get(column)?;
+    let bytes = bytes.ok_or_else(|| sqlx::Error::ColumnDecode {
+        index: column.to_string(),
+        source: "unexpected NULL".into(),
+    })?;
+    String::from_utf8(bytes).map_err(|e| sqlx::Error::ColumnDecode {
+        index: column.to_string(),
+        source: Box::new(e),
+    })
+}
+
+/// `get_string` of a nullable column.
+fn get_optional_string(row: &MySqlRow, column: &str) -> Result<Option<String>, sqlx::Error> {
+    optional_string_from(column, row.try_get(column)?)
+}
+
+fn optional_string_from(
+    column: &str,
+    bytes: Option<Vec<u8>>,
+) -> Result<Option<String>, sqlx::Error> {
+    bytes
+        .map(|bytes| {
+            String::from_utf8(bytes).map_err(|e| sqlx::Error::ColumnDecode {
+                index: column.to_string(),
+                source: Box::new(e),
+            })
+        })
+        .transpose()
+}
+
+/// The `mode` column is a VARCHAR holding the numeric `ProcessingMode`.
+fn get_mode(row: &MySqlRow) -> Result<u8, sqlx::Error> {
+    mode_from(&get_string(row, "mode")?)
+}
+
+fn mode_from(value: &str) -> Result<u8, sqlx::Error> {
+    value.parse::<u8>().map_err(|e| sqlx::Error::ColumnDecode {
+        index: "mode".to_string(),
+        source: Box::new(e),
+    })
+}
+
+/// `supervisor_id` is `VARBINARY(16)`; anything but NULL or 16 bytes is a decode error.
+fn get_supervisor_id(row: &MySqlRow) -> Result<Option<Uuid>, sqlx::Error> {
+    supervisor_id_from(row.try_get("supervisor_id")?)
+}
+
+fn supervisor_id_from(bytes: Option<Vec<u8>>) -> Result<Option<Uuid>, sqlx::Error> {
+    bytes
+        .map(|bytes| {
+            Uuid::from_slice(&bytes).map_err(|e| sqlx::Error::ColumnDecode {
+                index: "supervisor_id".to_string(),
+                source: Box::new(e),
+            })
+        })
+        .transpose()
+}
+
+/// Source id columns decoded by `get_source_id`.
+const SOURCE_ID_COLUMNS: [&str; 2] = ["id", "source_id"];
+
+/// Reads a source id whatever integer type the column has (`sources.id` differs
+/// between schemas: signed, unsigned, `BIGINT`), failing on values out of `u32`.
+fn get_source_id(row: &MySqlRow, column: &str) -> Result<u32, sqlx::Error> {
+    let value: i128 = match row.try_get::<u64, _>(column) {
+        Ok(value) => value.into(),
+        //signed column
+        Err(sqlx::Error::ColumnDecode { .. }) => row.try_get::<i64, _>(column)?.into(),
+        Err(e) => return Err(e),
+    };
+    source_id_from(value).map_err(|e| sqlx::Error::ColumnDecode {
+        index: column.to_string(),
+        source: e.into(),
+    })
+}
+
+fn source_id_from(value: i128) -> Result<u32, String> {
+    u32::try_from(value).map_err(|_| format!("source id {} is out of the u32 range", value))
+}
+
+/// Drops (and logs) a streamed row with an undecodable source id instead of
+/// failing the whole stream.
+fn skip_invalid_source_id<T>(
+    row: Result<T, sqlx::Error>,
+) -> future::Ready<Option<Result<T, sqlx::Error>>> {
+    future::ready(match row {
+        Err(sqlx::Error::ColumnDecode { index, source })
+            if SOURCE_ID_COLUMNS.contains(&index.as_str()) =>
+        {
+            warn!("Skipping a row with an invalid '{}': {}", index, source);
+            None
+        }
+        row => Some(row),
+    })
+}
+
+impl FromRow<'_, MySqlRow> for CandidateRow {
+    fn from_row(row: &MySqlRow) -> Result<Self, sqlx::Error> {
+        Ok(CandidateRow {
+            uuid: row.try_get("uuid")?,
+            source_id: get_source_id(row, "source_id")?,
+            state: get_string(row, "state")?,
+            mode: get_mode(row)?,
+            created_at: get_optional_string(row, "created_at")?,
+        })
+    }
+}
+
+impl FromRow<'_, MySqlRow> for ProcessRecord {
+    fn from_row(row: &MySqlRow) -> Result<Self, sqlx::Error> {
+        let failure_reason: Option<Vec<u8>> = row.try_get("failure_reason")?;
+        Ok(ProcessRecord {
+            uuid: row.try_get("uuid")?,
+            source_id: get_source_id(row, "source_id")?,
+            supervisor_id: get_supervisor_id(row)?,
+            state: get_string(row, "state")?,
+            mode: get_mode(row)?,
+            failure_reason: failure_reason
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+            created_at: get_optional_string(row, "created_at")?,
+            updated_at: get_string(row, "updated_at")?,
+        })
+    }
+}
+
+/// Pool serving the scheduling and assignment reads of `dispatcher_processes`
+/// (`PROCESS_READS_POOL`). Writes always go to `pd`; the rows never live in `mvp`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessReadsPool {
+    Pd,
+    /// The `pd` read replica. Claims stay conditional updates on the primary,
+    /// so a lagging candidate is only a missed claim.
+    PdRead,
+}
+
+impl FromStr for ProcessReadsPool {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pd" => Ok(ProcessReadsPool::Pd),
+            "pd_read" => Ok(ProcessReadsPool::PdRead),
+            "mvp" => Err("dispatcher_processes is never read from the mvp DB".to_string()),
+            other => Err(format!("unknown pool '{}'", other)),
+        }
+    }
+}
 
 pub struct DbRepository {
     pd_connection_pool: MySqlPool,
+    /// Optional `pd` read replica (`PD_READ_DATABASE_URL`), used for status reads
+    /// and, with `PROCESS_READS_POOL=pd_read`, for candidate reads.
+    pd_read_connection_pool: Option<MySqlPool>,
     mvp_connection_pool: MySqlPool,
+    process_reads_pool: ProcessReadsPool,
+    process_classes: ProcessClasses,
+    ping_query: String,
+    max_process_age_secs: Option<u64>,
+    error_is_terminal: bool,
 }
 
 impl DbRepository {
     pub async fn new(env_params: &EnvParams) -> Result<DbRepository, sqlx::Error> {
-        let pd_connection_pool = MySqlPoolOptions::new()
-            .max_connections(env_params.max_db_connections())
-            .connect(env_params.pd_db_url())
-            .await?;
+        let pd_connection_pool = Self::connect_pool(
+            "pd",
+            env_params.pd_db_url(),
+            env_params.pd_max_connections(),
+            env_params,
+        )
+        .await?;
 
-        let mvp_connection_pool = MySqlPoolOptions::new()
-            .max_connections(env_params.max_db_connections())
-            .connect(env_params.mvp_db_url())
-            .await?;
+        let mvp_connection_pool = Self::connect_pool(
+            "mvp",
+            env_params.mvp_db_url(),
+            env_params.mvp_max_connections(),
+            env_params,
+        )
+        .await?;
+
+        let pd_read_connection_pool = match env_params.pd_read_db_url() {
+            Some(url) => Some(
+                Self::connect_pool("pd_read", url, env_params.pd_max_connections(), env_params)
+                    .await?,
+            ),
+            None => None,
+        };
+
+        if env_params.auto_migrate() {
+            Self::run_pd_migrations(&pd_connection_pool).await?;
+        }
 
-        Ok(DbRepository {
+        let db_repository = DbRepository {
             pd_connection_pool,
+            pd_read_connection_pool,
             mvp_connection_pool,
-        })
+            process_reads_pool: env_params.process_reads_pool(),
+            process_classes: env_params.process_classes().clone(),
+            ping_query: env_params.db_ping_query().to_string(),
+            max_process_age_secs: env_params.max_process_age_secs(),
+            error_is_terminal: env_params.error_is_terminal(),
+        };
+        //fail fast if the probe query is not routable through the current DB setup
+        db_repository.ping().await?;
+        db_repository.assert_utc_session().await?;
+        db_repository.assert_expected_tables().await?;
+
+        Ok(db_repository)
     }
 
-    pub async fn available_source_ids_stream(
-        &self,
-    ) -> Result<
-        std::pin::Pin<Box<dyn Stream<Item = Result<sqlx::mysql::MySqlRow, sqlx::Error>> + Send>>,
-        sqlx::Error,
-    > {
-        let source_ids_to_process: std::pin::Pin<
-            Box<dyn Stream<Item = Result<sqlx::mysql::MySqlRow, sqlx::Error>> + Send>,
-        > = sqlx::query("SELECT id FROM sources where status = 'run'")
-            .fetch(&self.mvp_connection_pool);
+    /// Applies pending `dispatcher_processes` migrations. Shares the `_sqlx_migrations`
+    /// bookkeeping with `sqlx migrate run`, so both ways can be mixed.
+    async fn run_pd_migrations(pd_connection_pool: &MySqlPool) -> Result<(), sqlx::Error> {
+        let mut connection = pd_connection_pool.acquire().await?;
+        connection.ensure_migrations_table().await?;
+        let applied_versions: HashSet<i64> = connection
+            .list_applied_migrations()
+            .await?
+            .into_iter()
+            .map(|migration| migration.version)
+            .collect();
+        drop(connection);
+
+        PD_MIGRATOR.run(pd_connection_pool).await?;
+
+        let mut applied_cnt = 0;
+        for migration in PD_MIGRATOR.iter().filter(|migration| {
+            migration.migration_type.is_up_migration()
+                && !applied_versions.contains(&migration.version)
+        }) {
+            info!(
+                "Applied migration {} ({})",
+                migration.version, migration.description
+            );
+            applied_cnt += 1;
+        }
+        info!("pd migrations are up to date, {} applied", applied_cnt);
+        Ok(())
+    }
+
+    /// `Error` bound where it is still assignable / expirable. With `ERROR_IS_TERMINAL`
+    /// it is bound as `NULL`, which never matches `state = ?` or `state IN (…, ?)`.
+    fn retryable_error_state(&self) -> Option<String> {
+        (!self.error_is_terminal).then(|| DispatchState::Error.to_string())
+    }
+
+    async fn connect_pool(
+        pool_name: &str,
+        db_url: &str,
+        max_connections: u32,
+        env_params: &EnvParams,
+    ) -> Result<MySqlPool, sqlx::Error> {
+        let connect_options = Self::connect_options(db_url)?;
+        connect_with_retry(
+            pool_name,
+            env_params.db_connect_retries(),
+            Duration::from_millis(env_params.db_connect_base_delay_ms()),
+            || {
+                MySqlPoolOptions::new()
+                    .max_connections(max_connections)
+                    .connect_with(connect_options.clone())
+            },
+        )
+        .await
+    }
+
+    fn connect_options(db_url: &str) -> Result<MySqlConnectOptions, sqlx::Error> {
+        //overrides a `timezone` parameter from the URL as well
+        Ok(MySqlConnectOptions::from_str(db_url)?.timezone(DB_SESSION_TIMEZONE.to_string()))
+    }
+
+    /// Fails if `NOW()` on any pool is not UTC, e.g. when a proxy drops the
+    /// session `time_zone` set on connect.
+    async fn assert_utc_session(&self) -> Result<(), sqlx::Error> {
+        for (pool_name, pool) in self.pools() {
+            let offset_secs: i64 =
+                sqlx::query_scalar("SELECT TIMESTAMPDIFF(SECOND, UTC_TIMESTAMP(), NOW())")
+                    .fetch_one(pool)
+                    .await?;
+            if offset_secs.abs() > 1 {
+                return Err(sqlx::Error::Configuration(
+                    format!(
+                        "{} DB session timezone is not UTC (offset {}s), created_at values would be misread",
+                        pool_name, offset_secs
+                    )
+                    .into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if a pool does not see the table it is used for, e.g. when
+    /// `PD_DATABASE_URL` and `MVP_DATABASE_URL` are swapped.
+    async fn assert_expected_tables(&self) -> Result<(), sqlx::Error> {
+        for (pool_name, pool) in self.pools() {
+            let table = match pool_name {
+                "mvp" => "sources",
+                _ => "dispatcher_processes",
+            };
+            if !Self::table_exists(pool, table).await? {
+                return Err(sqlx::Error::Configuration(
+                    format!(
+                        "{} DB has no {} table, check its database URL (or run the migrations)",
+                        pool_name, table
+                    )
+                    .into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn table_exists(pool: &MySqlPool, table: &str) -> Result<bool, sqlx::Error> {
+        let table_cnt: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM information_schema.tables
+                 WHERE table_schema = DATABASE() AND table_name = ?",
+        )
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+        Ok(table_cnt > 0)
+    }
+
+    /// Pool of the `dispatcher_processes` reads that feed scheduling and assignment.
+    fn process_reads_pool(&self) -> &MySqlPool {
+        self.process_reads_pool_of(None)
+    }
+
+    /// `process_reads_pool`, unless a process class overrides it.
+    fn process_reads_pool_of(&self, class_reads_pool: Option<ProcessReadsPool>) -> &MySqlPool {
+        match (
+            class_reads_pool.unwrap_or(self.process_reads_pool),
+            &self.pd_read_connection_pool,
+        ) {
+            (ProcessReadsPool::PdRead, Some(pd_read_connection_pool)) => pd_read_connection_pool,
+            _ => &self.pd_connection_pool,
+        }
+    }
+
+    fn pools(&self) -> Vec<(&'static str, &MySqlPool)> {
+        let mut pools = vec![
+            ("pd", &self.pd_connection_pool),
+            ("mvp", &self.mvp_connection_pool),
+        ];
+        if let Some(pd_read_connection_pool) = &self.pd_read_connection_pool {
+            pools.push(("pd_read", pd_read_connection_pool));
+        }
+        pools
+    }
+}
+
+#[async_trait]
+impl ProcessStore for DbRepository {
+    /// Runs the configured probe query (`DB_PING_QUERY`) against every pool.
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        for (_, pool) in self.pools() {
+            sqlx::query(&self.ping_query).execute(pool).await?;
+        }
+        Ok(())
+    }
+
+    async fn ping_each(&self) -> Vec<(&'static str, Result<(), sqlx::Error>)> {
+        let mut pings = Vec::new();
+        for (name, pool) in self.pools() {
+            let ping = sqlx::query(&self.ping_query).execute(pool).await;
+            pings.push((name, ping.map(|_| ())));
+        }
+        pings
+    }
+
+    async fn db_version(&self) -> Result<String, sqlx::Error> {
+        let row = sqlx::query("SELECT VERSION() AS version")
+            .fetch_one(&self.pd_connection_pool)
+            .await?;
+        get_string(&row, "version")
+    }
+
+    async fn applied_migration_version(&self) -> Result<Option<i64>, sqlx::Error> {
+        //never create the bookkeeping table here, it is only read
+        if !Self::table_exists(&self.pd_connection_pool, "_sqlx_migrations").await? {
+            return Ok(None);
+        }
+        sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1")
+            .fetch_one(&self.pd_connection_pool)
+            .await
+    }
+
+    async fn available_source_ids_stream(&self) -> Result<RecordStream<u32>, sqlx::Error> {
+        let source_ids_to_process: RecordStream<u32> =
+            sqlx::query("SELECT id FROM sources where status = 'run'")
+                .fetch(&self.mvp_connection_pool)
+                .map(|row| get_source_id(&row?, "id"))
+                .filter_map(skip_invalid_source_id)
+                .boxed();
         Ok(source_ids_to_process)
     }
 
-    pub async fn insert_new_process(
+    async fn insert_new_process(
         &self,
         source_id: u32,
         state: DispatchState,
@@ -64,93 +448,567 @@ impl DbRepository {
         Ok(uuid_val)
     }
 
-    pub async fn get_latest_process_for(
+    async fn get_latest_process_for(
         &self,
         source_id: u32,
-    ) -> Result<Option<sqlx::mysql::MySqlRow>, sqlx::Error> {
-        let query = sqlx::query("SELECT * FROM dispatcher_processes WHERE source_id = ? ORDER BY created_at DESC LIMIT 1")
-            .bind(source_id);
+    ) -> Result<Option<ProcessRecord>, sqlx::Error> {
+        let query = sqlx::query(
+            "SELECT * FROM dispatcher_processes WHERE source_id = ? AND mode <> ?
+                 ORDER BY created_at DESC LIMIT 1",
+        )
+        .bind(source_id)
+        .bind(u8::from(ProcessingMode::Sandbox).to_string());
         let process = query.fetch_optional(&self.pd_connection_pool).await?;
-        Ok(process)
+        process.as_ref().map(ProcessRecord::from_row).transpose()
+    }
+
+    async fn has_unfinished_process(
+        &self,
+        source_id: u32,
+        processing_mode: ProcessingMode,
+    ) -> Result<bool, sqlx::Error> {
+        //without ERROR_IS_TERMINAL an Error process is retried, so it is not finished
+        let terminal_error_state = if self.error_is_terminal {
+            DispatchState::Error
+        } else {
+            DispatchState::Failed
+        };
+        let found: i64 = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM dispatcher_processes
+                 WHERE source_id = ? AND mode = ? AND state NOT IN (?, ?, ?))",
+        )
+        .bind(source_id)
+        .bind(u8::from(processing_mode).to_string())
+        .bind(DispatchState::Completed.to_string())
+        .bind(DispatchState::Failed.to_string())
+        .bind(terminal_error_state.to_string())
+        .fetch_one(&self.pd_connection_pool)
+        .await?;
+        Ok(found != 0)
     }
 
-    pub async fn get_available_source_processes_stream(
+    /// One query per `SOURCE_IDS_CHUNK_SIZE` ids instead of one per source.
+    async fn sources_needing_new_process(
+        &self,
+        active_source_ids: &[u32],
+        reprocess_cutoff: &str,
+    ) -> Result<Vec<u32>, sqlx::Error> {
+        //sandbox processes never block or enable scheduling
+        let sandbox_mode = u8::from(ProcessingMode::Sandbox).to_string();
+        let mut blocked_source_ids = HashSet::new();
+        for chunk in active_source_ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+            let mut query = QueryBuilder::<MySql>::new(
+                "SELECT p.source_id FROM dispatcher_processes p
+                     JOIN (SELECT source_id, MAX(created_at) AS created_at
+                             FROM dispatcher_processes WHERE mode <> ",
+            );
+            query
+                .push_bind(sandbox_mode.clone())
+                .push(" AND source_id IN (");
+            let mut separated = query.separated(", ");
+            for &source_id in chunk {
+                separated.push_bind(source_id);
+            }
+            query
+                .push(
+                    ") GROUP BY source_id) latest
+                     ON p.source_id = latest.source_id AND p.created_at = latest.created_at
+                        AND p.mode <> ",
+                )
+                .push_bind(sandbox_mode.clone())
+                .push(" WHERE p.state NOT IN (")
+                .push_bind(DispatchState::Completed.to_string())
+                .push(", ")
+                .push_bind(DispatchState::Failed.to_string());
+            if self.error_is_terminal {
+                query.push(", ").push_bind(DispatchState::Error.to_string());
+            }
+            query
+                .push(") OR p.created_at >= ")
+                .push_bind(reprocess_cutoff);
+
+            let source_ids: Vec<u32> = query
+                .build_query_scalar()
+                .fetch_all(&self.pd_connection_pool)
+                .await?;
+            blocked_source_ids.extend(source_ids);
+        }
+        Ok(active_source_ids
+            .iter()
+            .copied()
+            .filter(|source_id| !blocked_source_ids.contains(source_id))
+            .collect())
+    }
+
+    async fn promote_created_to_pending(&self, source_ids: &[u32]) -> Result<u64, sqlx::Error> {
+        let mut query = QueryBuilder::<MySql>::new("UPDATE dispatcher_processes SET state = ");
+        query
+            .push_bind(DispatchState::Pending.to_string())
+            .push(" WHERE state = ")
+            .push_bind(DispatchState::Created.to_string())
+            .push(" AND source_id IN (");
+        let mut separated = query.separated(", ");
+        for &source_id in source_ids {
+            separated.push_bind(source_id);
+        }
+        separated.push_unseparated(")");
+
+        let result = query.build().execute(&self.pd_connection_pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn get_available_source_processes_stream(
         &self,
         source_id: u32,
         limit: u32,
-    ) -> Result<
-        std::pin::Pin<Box<dyn Stream<Item = Result<sqlx::mysql::MySqlRow, sqlx::Error>> + Send>>,
-        sqlx::Error,
-    > {
+    ) -> Result<RecordStream<ProcessRecord>, sqlx::Error> {
         let query = sqlx::query(
-            "SELECT * FROM dispatcher_processes WHERE source_id = ? AND state IN (?, ?) ORDER BY created_at ASC LIMIT ?",
+            "SELECT * FROM dispatcher_processes WHERE source_id = ? AND state IN (?, ?)
+                 AND (? IS NULL OR created_at >= NOW(3) - INTERVAL ? SECOND)
+                 ORDER BY created_at ASC LIMIT ?",
         )
             .bind(source_id)
             .bind(DispatchState::Created.to_string())
             .bind(DispatchState::Pending.to_string())
+            .bind(self.max_process_age_secs)
+            .bind(self.max_process_age_secs)
             .bind(limit);
 
-        let processes_stream: std::pin::Pin<
-            Box<dyn Stream<Item = Result<sqlx::mysql::MySqlRow, sqlx::Error>> + Send>,
-        > = query.fetch(&self.mvp_connection_pool);
+        let processes_stream: RecordStream<ProcessRecord> = query
+            .fetch(self.process_reads_pool())
+            .map(|row| ProcessRecord::from_row(&row?))
+            .filter_map(skip_invalid_source_id)
+            .boxed();
 
         Ok(processes_stream)
     }
 
-    pub async fn get_available_processes_sources_stream(
+    async fn get_available_processes_sources_stream(
         &self,
         supervisor_id: Uuid,
         limit: u32,
-    ) -> Result<
-        std::pin::Pin<Box<dyn Stream<Item = Result<sqlx::mysql::MySqlRow, sqlx::Error>> + Send>>,
-        sqlx::Error,
-    > {
+    ) -> Result<RecordStream<CandidateRow>, sqlx::Error> {
+        //one query per class in priority order, each on the pool of its class
+        let mut class_streams = Vec::new();
+        for class in self.process_classes.iter() {
+            let query = sqlx::query(
+                "SELECT uuid, source_id, state, mode, created_at FROM dispatcher_processes
+                     WHERE ((state IN (?, ?) AND supervisor_id IS NULL) OR
+                            (state = ? AND supervisor_id = ?))
+                       AND FIND_IN_SET(mode, ?) > 0
+                       AND (? IS NULL OR created_at >= NOW(3) - INTERVAL ? SECOND)
+                     ORDER BY created_at ASC LIMIT ?",
+            )
+            .bind(DispatchState::Created.to_string())
+            .bind(DispatchState::Pending.to_string())
+            .bind(self.retryable_error_state())
+            .bind(supervisor_id)
+            .bind(class.mode_values())
+            .bind(self.max_process_age_secs)
+            .bind(self.max_process_age_secs)
+            .bind(limit);
+            class_streams.push(
+                query
+                    .fetch(self.process_reads_pool_of(class.reads_pool))
+                    .map(|row| CandidateRow::from_row(&row?)),
+            );
+        }
+
+        let candidates_stream: RecordStream<CandidateRow> = futures::stream::iter(class_streams)
+            .flatten()
+            .filter_map(skip_invalid_source_id)
+            .take(limit as usize)
+            .boxed();
+
+        Ok(candidates_stream)
+    }
+
+    async fn non_finished_processes_stream(
+        &self,
+        limit: u32,
+    ) -> Result<RecordStream<ProcessRecord>, sqlx::Error> {
         let query = sqlx::query(
-            "SELECT source_id FROM dispatcher_processes
-                 WHERE (state IN (?, ?) AND supervisor_id IS NULL) OR 
-                       (state = ? AND supervisor_id IS ?)
+            "SELECT * FROM dispatcher_processes
+                 WHERE state NOT IN (?, ?)
                  ORDER BY created_at ASC LIMIT ?",
         )
-        .bind(DispatchState::Created.to_string())
-        .bind(DispatchState::Pending.to_string())
-        .bind(DispatchState::Error.to_string())
-        .bind(supervisor_id)
+        .bind(DispatchState::Completed.to_string())
+        .bind(DispatchState::Failed.to_string())
         .bind(limit);
 
-        let processes_stream: std::pin::Pin<
-            Box<dyn Stream<Item = Result<sqlx::mysql::MySqlRow, sqlx::Error>> + Send>,
-        > = query.fetch(&self.mvp_connection_pool);
+        let processes_stream: RecordStream<ProcessRecord> = query
+            .fetch(&self.pd_connection_pool)
+            .map(|row| ProcessRecord::from_row(&row?))
+            .boxed();
 
         Ok(processes_stream)
     }
 
-    pub async fn assign_process_to_supervisor(
+    async fn try_claim_process(
+        &self,
+        id: Uuid,
+        supervisor_id: Uuid,
+        new_state: DispatchState,
+    ) -> Result<u64, sqlx::Error> {
+        //the candidate was read without a row lock, possibly from another pool:
+        //only the supervisor whose UPDATE still sees it waiting and unowned wins
+        let query = sqlx::query(
+            "UPDATE dispatcher_processes SET supervisor_id = ?, state = ?
+                 WHERE uuid = ? AND supervisor_id IS NULL AND state IN (?, ?)",
+        )
+        .bind(supervisor_id)
+        .bind(new_state.to_string())
+        .bind(id)
+        .bind(DispatchState::Created.to_string())
+        .bind(DispatchState::Pending.to_string());
+
+        let result = query.execute(&self.pd_connection_pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn claim_process_by_id(
         &self,
         id: Uuid,
         supervisor_id: Uuid,
         assigned_state: DispatchState,
-    ) -> Result<(), sqlx::Error> {
+    ) -> Result<u64, sqlx::Error> {
         let query = sqlx::query(
-            "UPDATE dispatcher_processes SET supervisor_id = ?, state = ? WHERE uuid = ?",
+            "UPDATE dispatcher_processes SET supervisor_id = ?, state = ?
+                 WHERE uuid = ? AND supervisor_id IS NULL AND state IN (?, ?, ?)
+                   AND (? IS NULL OR created_at >= NOW(3) - INTERVAL ? SECOND)",
         )
         .bind(supervisor_id)
         .bind(assigned_state.to_string())
-        .bind(id);
+        .bind(id)
+        .bind(DispatchState::Created.to_string())
+        .bind(DispatchState::Pending.to_string())
+        .bind(self.retryable_error_state())
+        .bind(self.max_process_age_secs)
+        .bind(self.max_process_age_secs);
 
-        query.execute(&self.pd_connection_pool).await?;
-        Ok(())
+        let result = query.execute(&self.pd_connection_pool).await?;
+        Ok(result.rows_affected())
     }
 
-    pub async fn update_process_state(
+    async fn claim_candidate(
         &self,
         id: Uuid,
-        state: DispatchState,
+        supervisor_id: Uuid,
+        assigned_state: DispatchState,
+    ) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query(
+            "UPDATE dispatcher_processes SET supervisor_id = ?, state = ?
+                 WHERE uuid = ?
+                   AND ((state IN (?, ?) AND supervisor_id IS NULL) OR
+                        (state = ? AND supervisor_id = ?))",
+        )
+        .bind(supervisor_id)
+        .bind(assigned_state.to_string())
+        .bind(id)
+        .bind(DispatchState::Created.to_string())
+        .bind(DispatchState::Pending.to_string())
+        .bind(self.retryable_error_state())
+        .bind(supervisor_id);
+
+        let result = query.execute(&self.pd_connection_pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn count_queued_before(
+        &self,
+        source_id: Option<u32>,
+        created_at: &str,
     ) -> Result<u64, sqlx::Error> {
-        let query = sqlx::query("UPDATE dispatcher_processes SET state = ? WHERE uuid = ?")
-            .bind(state.to_string())
-            .bind(id);
+        let cnt: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM dispatcher_processes
+                 WHERE state IN (?, ?) AND supervisor_id IS NULL
+                   AND (? IS NULL OR source_id = ?)
+                   AND created_at < ?
+                   AND (? IS NULL OR created_at >= NOW(3) - INTERVAL ? SECOND)",
+        )
+        .bind(DispatchState::Created.to_string())
+        .bind(DispatchState::Pending.to_string())
+        .bind(source_id)
+        .bind(source_id)
+        .bind(created_at)
+        .bind(self.max_process_age_secs)
+        .bind(self.max_process_age_secs)
+        .fetch_one(&self.pd_connection_pool)
+        .await?;
+        Ok(cnt as u64)
+    }
+
+    async fn count_queued_processes(&self) -> Result<u64, sqlx::Error> {
+        let cnt: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM dispatcher_processes
+                 WHERE state IN (?, ?) AND supervisor_id IS NULL
+                   AND (? IS NULL OR created_at >= NOW(3) - INTERVAL ? SECOND)",
+        )
+        .bind(DispatchState::Created.to_string())
+        .bind(DispatchState::Pending.to_string())
+        .bind(self.max_process_age_secs)
+        .bind(self.max_process_age_secs)
+        .fetch_one(&self.pd_connection_pool)
+        .await?;
+        Ok(cnt as u64)
+    }
+
+    async fn count_in_flight_processes(&self) -> Result<u64, sqlx::Error> {
+        let cnt: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM dispatcher_processes
+                 WHERE state = ? AND supervisor_id IS NOT NULL",
+        )
+        .bind(DispatchState::Processing.to_string())
+        .fetch_one(&self.pd_connection_pool)
+        .await?;
+        Ok(cnt as u64)
+    }
+
+    async fn get_process(&self, id: Uuid) -> Result<Option<ProcessRecord>, sqlx::Error> {
+        let query = sqlx::query("SELECT * FROM dispatcher_processes WHERE uuid = ?").bind(id);
+        let process = query.fetch_optional(&self.pd_connection_pool).await?;
+        process.as_ref().map(ProcessRecord::from_row).transpose()
+    }
+
+    async fn list_processes(
+        &self,
+        filter: &ProcessFilter,
+    ) -> Result<Vec<ProcessRecord>, sqlx::Error> {
+        let mut query =
+            QueryBuilder::<MySql>::new("SELECT * FROM dispatcher_processes WHERE 1 = 1");
+        if let Some(source_id) = filter.source_id {
+            query.push(" AND source_id = ").push_bind(source_id);
+        }
+        if let Some(state) = &filter.state {
+            query.push(" AND state = ").push_bind(state.to_string());
+        }
+        query
+            .push(" ORDER BY created_at DESC LIMIT ")
+            .push_bind(filter.limit)
+            .push(" OFFSET ")
+            .push_bind(filter.offset);
+
+        let rows = query.build().fetch_all(&self.pd_connection_pool).await?;
+        rows.iter().map(ProcessRecord::from_row).collect()
+    }
+
+    /// Reads from the read replica if one is configured, unless `from_primary` is set.
+    async fn get_process_for_status(
+        &self,
+        id: Uuid,
+        from_primary: bool,
+    ) -> Result<Option<ProcessRecord>, sqlx::Error> {
+        let pool = match &self.pd_read_connection_pool {
+            Some(pd_read_connection_pool) if !from_primary => pd_read_connection_pool,
+            _ => &self.pd_connection_pool,
+        };
+        let query = sqlx::query("SELECT * FROM dispatcher_processes WHERE uuid = ?").bind(id);
+        let process = query.fetch_optional(pool).await?;
+        process.as_ref().map(ProcessRecord::from_row).transpose()
+    }
+
+    async fn update_process_state(
+        &self,
+        id: Uuid,
+        state: DispatchState,
+    ) -> Result<Option<ProcessRecord>, sqlx::Error> {
+        //one connection of the primary, so the read back sees the UPDATE
+        let mut connection = self.pd_connection_pool.acquire().await?;
+        let query = sqlx::query(
+            "UPDATE dispatcher_processes SET state = ?
+                 WHERE uuid = ? AND state = ? AND supervisor_id IS NOT NULL",
+        )
+        .bind(state.to_string())
+        .bind(id)
+        .bind(DispatchState::Processing.to_string());
+        if query.execute(&mut *connection).await?.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let query = sqlx::query("SELECT * FROM dispatcher_processes WHERE uuid = ?").bind(id);
+        let process = query.fetch_optional(&mut *connection).await?;
+        process.as_ref().map(ProcessRecord::from_row).transpose()
+    }
+
+    async fn release_process(&self, id: Uuid) -> Result<u64, sqlx::Error> {
+        //as in has_unfinished_process, Error is finished with ERROR_IS_TERMINAL
+        let terminal_error_state = if self.error_is_terminal {
+            DispatchState::Error
+        } else {
+            DispatchState::Failed
+        };
+        //a queued process nobody holds has nothing to release
+        let query = sqlx::query(
+            "UPDATE dispatcher_processes SET state = ?, supervisor_id = NULL
+                 WHERE uuid = ? AND state NOT IN (?, ?, ?)
+                   AND (supervisor_id IS NOT NULL OR state IN (?, ?))",
+        )
+        .bind(DispatchState::Created.to_string())
+        .bind(id)
+        .bind(DispatchState::Completed.to_string())
+        .bind(DispatchState::Failed.to_string())
+        .bind(terminal_error_state.to_string())
+        .bind(DispatchState::Processing.to_string())
+        .bind(DispatchState::Error.to_string());
+
+        let result = query.execute(&self.pd_connection_pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn reset_supervisor_processes(&self, supervisor_id: Uuid) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query(
+            "UPDATE dispatcher_processes SET state = ?, supervisor_id = NULL
+                 WHERE supervisor_id = ? AND state = ?",
+        )
+        .bind(DispatchState::Created.to_string())
+        .bind(supervisor_id)
+        .bind(DispatchState::Processing.to_string());
+
+        let result = query.execute(&self.pd_connection_pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn reset_stale_processes(&self, older_than: Duration) -> Result<u64, sqlx::Error> {
+        let query = sqlx::query(
+            "UPDATE dispatcher_processes SET state = ?, supervisor_id = NULL
+                 WHERE state = ? AND updated_at < NOW(3) - INTERVAL ? SECOND",
+        )
+        .bind(DispatchState::Created.to_string())
+        .bind(DispatchState::Processing.to_string())
+        .bind(older_than.as_secs());
+
+        let result = query.execute(&self.pd_connection_pool).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn expire_old_processes(&self, failure_reason: &str) -> Result<u64, sqlx::Error> {
+        let Some(max_process_age_secs) = self.max_process_age_secs else {
+            return Ok(0);
+        };
+        let query = sqlx::query(
+            "UPDATE dispatcher_processes SET state = ?, failure_reason = ?
+                 WHERE state IN (?, ?, ?) AND created_at < NOW(3) - INTERVAL ? SECOND",
+        )
+        .bind(DispatchState::Failed.to_string())
+        .bind(failure_reason)
+        .bind(DispatchState::Created.to_string())
+        .bind(DispatchState::Pending.to_string())
+        .bind(self.retryable_error_state())
+        .bind(max_process_age_secs);
 
         let result = query.execute(&self.pd_connection_pool).await?;
         Ok(result.rows_affected())
     }
 }
+
+/// Runs `connect` until it succeeds or `retries` retries after the first attempt
+/// are used up, doubling `base_delay` after every failure (e.g. a DB still
+/// booting next to the dispatcher). Returns the error of the last attempt.
+async fn connect_with_retry<F, Fut>(
+    pool_name: &str,
+    retries: u32,
+    base_delay: Duration,
+    mut connect: F,
+) -> Result<MySqlPool, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<MySqlPool, sqlx::Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        match connect().await {
+            Err(e) if attempt < retries => {
+                let delay = base_delay.saturating_mul(2u32.saturating_pow(attempt));
+                attempt += 1;
+                warn!(
+                    "{}: connect failed, retrying in {:?} ({}/{}): {}",
+                    pool_name, delay, attempt, retries, e
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => {
+                warn!(
+                    "{}: connect failed, giving up after {} retries: {}",
+                    pool_name, retries, e
+                );
+                return Err(e);
+            }
+            Ok(pool) => return Ok(pool),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_connect_gives_up_after_retries() {
+        let attempts = AtomicU32::new(0);
+        //nothing listens on port 1
+        let connect_options = MySqlConnectOptions::from_str("mysql://pd@127.0.0.1:1/pd").unwrap();
+
+        let res = connect_with_retry("pd", 2, Duration::from_millis(1), || {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            MySqlPoolOptions::new()
+                .acquire_timeout(Duration::from_millis(100))
+                .connect_with(connect_options.clone())
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_source_id_in_range() {
+        assert_eq!(source_id_from(0), Ok(0));
+        assert_eq!(source_id_from(u32::MAX.into()), Ok(u32::MAX));
+    }
+
+    #[test]
+    fn test_negative_source_id_is_rejected() {
+        assert_eq!(
+            source_id_from(-1),
+            Err("source id -1 is out of the u32 range".to_string())
+        );
+    }
+
+    #[test]
+    fn test_oversized_source_id_is_rejected() {
+        assert!(source_id_from(i128::from(u32::MAX) + 1).is_err());
+        assert!(source_id_from(i64::MAX.into()).is_err());
+    }
+
+    #[test]
+    fn test_created_at_column_mapping() {
+        assert_eq!(
+            optional_string_from("created_at", Some(b"2024-10-13 13:13:23.000".to_vec())).unwrap(),
+            Some("2024-10-13 13:13:23.000".to_string())
+        );
+        assert_eq!(optional_string_from("created_at", None).unwrap(), None);
+        assert!(matches!(
+            optional_string_from("created_at", Some(vec![0xff])),
+            Err(sqlx::Error::ColumnDecode { index, .. }) if index == "created_at"
+        ));
+    }
+
+    #[test]
+    fn test_supervisor_id_column_mapping() {
+        let supervisor_id = Uuid::new_v4();
+
+        assert_eq!(
+            supervisor_id_from(Some(supervisor_id.as_bytes().to_vec())).unwrap(),
+            Some(supervisor_id)
+        );
+        assert_eq!(supervisor_id_from(None).unwrap(), None);
+        assert!(supervisor_id_from(Some(vec![1, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn test_mode_column_mapping() {
+        assert_eq!(mode_from("2").unwrap(), 2);
+        assert!(mode_from("sandbox").is_err());
+    }
+}
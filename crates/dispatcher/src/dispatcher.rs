@@ -1,56 +1,135 @@
+mod drain;
 mod error;
+mod latency;
+pub mod lifecycle;
+mod lock_retry;
+mod post_commit_hooks;
+mod recency;
+mod recent_writes;
+mod source_ids_cache;
+mod stats;
 
-use super::db_repository::DbRepository;
 use crate::async_keyed_mutex::AsyncKeyedMutex;
-use crate::cancellation_ext::CancellationExt;
+use crate::cancellation_ext::{sleep_unless_cancelled, CancellationError, CancellationExt};
 use crate::env::EnvParams;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::process_class::ProcessClasses;
+use crate::process_store::{ProcessFilter, ProcessRecord, ProcessStore, SOURCE_IDS_CHUNK_SIZE};
+use drain::DrainState;
+pub use drain::DrainStatus;
+use latency::SourceLatencies;
+pub use latency::{LatencyHistogram, SourceLatency, LATENCY_BUCKET_BOUNDS_SECS};
+use lock_retry::{is_connection_error, retry_on_lock_conflict};
+use recency::SourceRecency;
+pub use recency::ScanStrategy;
+use recent_writes::RecentWrites;
+use source_ids_cache::SourceIdsCache;
+use stats::DispatcherStats;
+pub use stats::StatsSnapshot;
+use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc};
 use chrono_tz::Tz;
 use chrono_tz::Tz::UTC;
 pub use error::DispatcherError;
-use futures::stream::TryStreamExt;
+pub use post_commit_hooks::{HookError, PostCommitHook, PostCommitHooks, ProcessEvent};
+use lifecycle::LifecycleEvents;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
 use shared::{
-    AssignedProcess, DispatchState, ProcessingMode, REPORT_STATUS_ERROR, REPORT_STATUS_SUCCESS,
+    AssignedProcess, DispatchState, DispatcherInfo, ProcessStatus, ProcessingMode, QueuePosition,
+    ASSIGN_REASON_ALL_TAKEN, ASSIGN_REASON_DRAINING, ASSIGN_REASON_ERROR, ASSIGN_REASON_NO_SOURCES,
+    ASSIGN_REASON_PROBE_CAP_REACHED, REPORT_STATUS_ERROR, REPORT_STATUS_SUCCESS,
 };
-use sqlx::mysql::MySqlRow;
-use sqlx::Row;
+use std::collections::{BTreeMap, HashSet};
 use std::str::FromStr;
-use tracing::{error, info, trace};
-
-/// Workaround for sqlx treating VARCHAR columns as VARBINARY under utf8mb4_bin collation.
-trait MySqlRowExt {
-    fn get_string(&self, column: &str) -> String;
-}
-
-impl MySqlRowExt for MySqlRow {
-    fn get_string(&self, column: &str) -> String {
-        let bytes: Vec<u8> = self
-            .try_get(column)
-            .unwrap_or_else(|e| panic!("Unexpected '{column}' result from DB: {e}"));
-        String::from_utf8(bytes).unwrap_or_else(|_| panic!("Invalid UTF-8 in '{column}'"))
-    }
-}
+use tracing::{error, info, trace, warn};
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-const TIMEZONE: &str = "Europe/Berlin";
+const EXPIRED_FAILURE_REASON: &str = "expired (too old)";
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct Dispatcher {
-    db_repository: DbRepository,
+    process_store: Arc<dyn ProcessStore>,
     source_locks: Arc<AsyncKeyedMutex<u32, tokio::sync::Mutex<()>>>,
+    source_ids_cache: SourceIdsCache,
+    post_commit_hooks: PostCommitHooks,
+    stats: DispatcherStats,
+    source_latencies: SourceLatencies,
+    /// Unix millis of the last successful `prepare_schedule` cycle, `0` = none yet.
+    schedule_last_success: AtomicI64,
+    /// Last source id handled by a cycle cut short by `MAX_CREATES_PER_CYCLE`, `0` = none.
+    schedule_cursor: AtomicU32,
+    max_creates_per_cycle: Option<u16>,
+    schedule_retry_attempts: u32,
+    schedule_retry_backoff: Duration,
+    ready_requires_schedule: bool,
+    export_max_rows: u32,
+    drain: DrainState,
+    assign_max_probes: u32,
+    recent_writes: RecentWrites,
+    error_is_terminal: bool,
+    scan_strategy: ScanStrategy,
+    null_created_at: NullCreatedAt,
+    source_recency: SourceRecency,
+    process_classes: ProcessClasses,
+    time_formatter: DispatchTimeFormatter,
+    schedule_interval: Duration,
+    stale_process_after: Option<Duration>,
+    stale_process_sweep_interval: Duration,
     //TODO: move cancellation_token here and use as dispatcher property
 }
 
 impl Dispatcher {
-    pub async fn new(env_params: &EnvParams) -> Result<Dispatcher, sqlx::Error> {
-        let db_repository = DbRepository::new(env_params).await?;
+    pub fn new(env_params: &EnvParams, process_store: Arc<dyn ProcessStore>) -> Dispatcher {
         let source_locks = Arc::new(AsyncKeyedMutex::<u32>::new());
-        Ok(Dispatcher {
-            db_repository,
+        let source_ids_cache =
+            SourceIdsCache::new(Duration::from_secs(env_params.source_cache_ttl_secs()));
+        let post_commit_hooks = PostCommitHooks::default();
+        if env_params.lifecycle_events() {
+            post_commit_hooks.register(Arc::new(LifecycleEvents));
+        }
+        Dispatcher {
+            process_store,
             source_locks,
-        })
+            source_ids_cache,
+            post_commit_hooks,
+            stats: DispatcherStats::default(),
+            source_latencies: SourceLatencies::default(),
+            schedule_last_success: AtomicI64::new(0),
+            schedule_cursor: AtomicU32::new(0),
+            max_creates_per_cycle: env_params.max_creates_per_cycle(),
+            schedule_retry_attempts: env_params.schedule_retry_attempts(),
+            schedule_retry_backoff: Duration::from_millis(env_params.schedule_retry_backoff_ms()),
+            ready_requires_schedule: env_params.ready_requires_schedule(),
+            export_max_rows: env_params.export_max_rows(),
+            drain: DrainState::default(),
+            assign_max_probes: env_params.assign_max_probes(),
+            recent_writes: RecentWrites::new(Duration::from_secs(
+                env_params.read_your_writes_secs(),
+            )),
+            error_is_terminal: env_params.error_is_terminal(),
+            scan_strategy: env_params.scan_strategy(),
+            null_created_at: env_params.null_created_at(),
+            source_recency: SourceRecency::default(),
+            process_classes: env_params.process_classes().clone(),
+            time_formatter: DispatchTimeFormatter::new(env_params.timezone()),
+            schedule_interval: Duration::from_secs(env_params.schedule_interval_secs()),
+            stale_process_after: env_params.stale_process_secs().map(Duration::from_secs),
+            stale_process_sweep_interval: Duration::from_secs(
+                env_params.stale_process_sweep_secs(),
+            ),
+        }
+    }
+
+    /// Pause between two schedule cycles of the main loop (`SCHEDULE_INTERVAL_SECS`).
+    pub fn schedule_interval(&self) -> Duration {
+        self.schedule_interval
+    }
+
+    /// Side effects to run after a process state change is committed to DB.
+    pub fn post_commit_hooks(&self) -> &PostCommitHooks {
+        &self.post_commit_hooks
     }
 
     pub fn start_clean_source_locks(&self) {
@@ -64,68 +143,439 @@ impl Dispatcher {
         });
     }
 
+    /// Periodically fails processes older than `MAX_PROCESS_AGE_SECS` that never started.
+    pub fn start_expire_old_processes(self: Arc<Self>, cancellation_token: CancellationToken) {
+        tokio::task::spawn(async move {
+            info!("Expiring old processes...");
+            loop {
+                match self
+                    .process_store
+                    .expire_old_processes(EXPIRED_FAILURE_REASON)
+                    .with_cancellation::<DispatcherError>(
+                        &cancellation_token,
+                        "expire_old_processes",
+                    )
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(cnt) => {
+                        info!("{} processes expired as too old", cnt);
+                        self.stats.add_aged_out_processes(cnt);
+                    }
+                    Err(DispatcherError::TerminatingSignalReceived) => break,
+                    Err(e) => error!("Error expiring old processes: {}", e),
+                }
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+                    _ = tokio::time::sleep(Duration::from_secs(60)) => {}
+                }
+            }
+        });
+    }
+
+    /// Every `STALE_PROCESS_SWEEP_SECS`, requeues processes left `Processing` for
+    /// longer than `STALE_PROCESS_SECS`. Does nothing when `STALE_PROCESS_SECS` is unset.
+    pub fn start_reap_stale_processes(self: Arc<Self>, cancellation_token: CancellationToken) {
+        let Some(stale_after) = self.stale_process_after else {
+            return;
+        };
+        tokio::task::spawn(async move {
+            info!("Reaping stale processes...");
+            loop {
+                match self
+                    .reap_stale_processes(stale_after)
+                    .with_cancellation::<DispatcherError>(
+                        &cancellation_token,
+                        "reap_stale_processes",
+                    )
+                    .await
+                {
+                    Ok(cnt) => info!("{} stale processing processes requeued", cnt),
+                    Err(DispatcherError::TerminatingSignalReceived) => break,
+                    Err(e) => error!("Error reaping stale processes: {}", e),
+                }
+                if !sleep_unless_cancelled(&cancellation_token, self.stale_process_sweep_interval)
+                    .await
+                {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Requeues `Processing` processes whose `updated_at` is older than `stale_after`
+    /// as `Created` and unassigned. Returns the number of requeued processes.
+    pub async fn reap_stale_processes(&self, stale_after: Duration) -> Result<u64, sqlx::Error> {
+        retry_on_lock_conflict(
+            "reap_stale_processes:reset_stale_processes",
+            || self.stats.add_lock_retry(),
+            || self.process_store.reset_stale_processes(stale_after),
+        )
+        .await
+    }
+
+    pub fn drain_status(&self) -> DrainStatus {
+        self.drain.status()
+    }
+
+    /// Stops assigning new work, waits until no assigned process is `Processing`
+    /// or `timeout` elapses, then cancels the token to shut the application down.
+    /// Returns `None` if a drain has already been started.
+    pub fn start_drain_and_exit(
+        self: Arc<Self>,
+        timeout: Duration,
+        cancellation_token: CancellationToken,
+    ) -> Option<tokio::task::JoinHandle<DrainStatus>> {
+        if !self.drain.start() {
+            return None;
+        }
+        warn!("Draining: no new processes are assigned, exiting within {:?}", timeout);
+        Some(tokio::task::spawn(async move {
+            let started_at = std::time::Instant::now();
+            loop {
+                let status = match self
+                    .process_store
+                    .count_in_flight_processes()
+                    .with_cancellation::<DispatcherError>(
+                        &cancellation_token,
+                        "drain_and_exit:count_in_flight",
+                    )
+                    .await
+                {
+                    Ok(in_flight) => {
+                        DrainStatus::after_poll(in_flight, started_at.elapsed(), timeout)
+                    }
+                    Err(DispatcherError::TerminatingSignalReceived) => break,
+                    Err(e) => {
+                        error!("Error counting in-flight processes: {}", e);
+                        //keep the last known count
+                        let in_flight = self.drain.status().in_flight();
+                        if started_at.elapsed() < timeout {
+                            DrainStatus::Draining { in_flight }
+                        } else {
+                            DrainStatus::TimedOut { in_flight }
+                        }
+                    }
+                };
+                self.drain.set(status.clone());
+                if status.is_final() {
+                    info!("Drain finished: {:?}", status);
+                    break;
+                }
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+                    _ = tokio::time::sleep(DRAIN_POLL_INTERVAL) => {}
+                }
+            }
+            cancellation_token.cancel();
+            self.drain.status()
+        }))
+    }
+
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// Per-source locks currently held or awaited (see `AsyncKeyedMutex::len`).
+    pub fn source_locks_len(&self) -> usize {
+        self.source_locks.len()
+    }
+
+    /// Created → finished latency of the `limit` sources with the most finished processes.
+    pub fn busiest_source_latencies(&self, limit: usize) -> Vec<SourceLatency> {
+        self.source_latencies.busiest(limit)
+    }
+
+    /// Created → finished latency histograms by source bucket (`source_id % 16`).
+    pub fn latency_histograms(&self) -> BTreeMap<u32, LatencyHistogram> {
+        self.source_latencies.histograms()
+    }
+
+    /// Unassigned `Created` / `Pending` processes waiting for a supervisor.
+    pub async fn queue_depth(&self) -> Result<u64, DispatcherError> {
+        Ok(self.process_store.count_queued_processes().await?)
+    }
+
+    /// `Processing` processes owned by a supervisor.
+    pub async fn in_flight_count(&self) -> Result<u64, DispatcherError> {
+        Ok(self.process_store.count_in_flight_processes().await?)
+    }
+
+    /// Runs one scheduling cycle. Cancelling the token aborts the pending DB call
+    /// with `DispatcherError::TerminatingSignalReceived`; there is no other
+    /// shutdown channel.
+    ///
+    /// ```no_run
+    /// # use process_dispatcher::db_repository::DbRepository;
+    /// # use process_dispatcher::dispatcher::{Dispatcher, DispatcherError};
+    /// # use std::sync::Arc;
+    /// # use tokio_util::sync::CancellationToken;
+    /// # async fn run() -> Result<(), DispatcherError> {
+    /// let env_params = process_dispatcher::env::fetch_env_params();
+    /// let db_repository = DbRepository::new(&env_params).await?;
+    /// let dispatcher = Dispatcher::new(&env_params, Arc::new(db_repository));
+    ///
+    /// let cancellation_token = CancellationToken::new();
+    /// match dispatcher.prepare_schedule(&cancellation_token).await {
+    ///     Ok(report) => println!("{} processes created", report.processes_created),
+    ///     Err(DispatcherError::TerminatingSignalReceived) => println!("shutting down"),
+    ///     Err(e) => return Err(e),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn prepare_schedule(
         &self,
         cancellation_token: &CancellationToken,
-    ) -> Result<u16, DispatcherError> {
+    ) -> Result<ScheduleReport, DispatcherError> {
         info!("Preparing schedule...");
 
         let mut created_cnt: u16 = 0;
-        //requesting a stream (sending a request to DB without waiting for the response)
-        let mut source_ids_to_process = self
-            .db_repository
-            .available_source_ids_stream()
+        let source_ids = match self.source_ids_cache.get() {
+            Some(source_ids) => {
+                trace!("Using {} cached source ids", source_ids.len());
+                source_ids
+            }
+            None => {
+                let source_ids = Arc::new(
+                    self.fetch_active_source_ids()
+                        .with_cancellation::<DispatcherError>(
+                            cancellation_token,
+                            "prepare_schedule:fetch_source_ids",
+                        )
+                        .await?,
+                );
+                self.source_ids_cache.set(source_ids.clone());
+                source_ids
+            }
+        };
+
+        let scanned_cnt = source_ids.len();
+
+        //one query for all sources instead of a latest-process lookup per source
+        let reprocess_cutoff =
+            DispatchTimeFormatter::dt_to_db(self.time_formatter.today_start_dt());
+        let source_ids = self
+            .process_store
+            .sources_needing_new_process(&source_ids, &reprocess_cutoff)
             .with_cancellation::<DispatcherError>(
                 cancellation_token,
-                "prepare_schedule:stream_creation",
+                "prepare_schedule:sources_needing_new_process",
             )
             .await?;
+        trace!("{} sources need a new process", source_ids.len());
 
-        //fetching result rows from the stream
-        while let Some(row) = source_ids_to_process
-            .try_next()
-            .with_cancellation::<DispatcherError>(
-                cancellation_token,
-                "prepare_schedule:stream_processing",
-            )
-            .await?
-        {
-            let source_id: u32 = row.try_get("id").expect("unexpected source id result");
+        //fifo continues after the last source of a capped cycle, so no source is starved;
+        //a score order cannot be resumed by source id, so recency always starts from the
+        //top and relies on handled sources dropping out of sources_needing_new_process
+        let source_ids = match self.scan_strategy {
+            ScanStrategy::Fifo => {
+                resume_order(source_ids, self.schedule_cursor.load(Ordering::Relaxed))
+            }
+            ScanStrategy::Recency => self.source_recency.order(resume_order(source_ids, 0)),
+        };
+        let mut deferred_cnt = 0;
+        let mut failed_cnt = 0;
+        for (idx, &source_id) in source_ids.iter().enumerate() {
+            if self
+                .max_creates_per_cycle
+                .is_some_and(|max_creates| created_cnt >= max_creates)
+            {
+                deferred_cnt = source_ids.len() - idx;
+                break;
+            }
             trace!("Processing source id: {}...", source_id);
 
-            let lock = self.source_locks.get_mutex(source_id);
             let res = self.process_source(source_id, cancellation_token).await;
             if let Err(e) = res {
                 error!("Error processing source id {}: {}", source_id, e);
+                failed_cnt += 1;
             } else {
                 created_cnt += res.unwrap();
             }
-            drop(lock);
+            if self.scan_strategy == ScanStrategy::Fifo {
+                self.schedule_cursor.store(source_id, Ordering::Relaxed);
+            }
+        }
+        if deferred_cnt > 0 {
+            info!(
+                "MAX_CREATES_PER_CYCLE reached: {} processes created, {} sources deferred",
+                created_cnt, deferred_cnt
+            );
+        } else {
+            self.schedule_cursor.store(0, Ordering::Relaxed);
+        }
+
+        self.schedule_last_success
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+        self.stats.add_processes_created(u64::from(created_cnt));
+        Ok(ScheduleReport {
+            sources_scanned: scanned_cnt,
+            processes_created: created_cnt,
+            sources_skipped: scanned_cnt - usize::from(created_cnt) - deferred_cnt - failed_cnt,
+            sources_deferred: deferred_cnt,
+            sources_failed: failed_cnt,
+        })
+    }
+
+    /// `prepare_schedule`, re-run after a short linear backoff on connection errors
+    /// (up to `SCHEDULE_RETRY_ATTEMPTS` times), so a network blip does not pause
+    /// scheduling for a whole interval. Any other error is returned immediately.
+    pub async fn prepare_schedule_with_retry(
+        &self,
+        cancellation_token: &CancellationToken,
+    ) -> Result<ScheduleReport, DispatcherError> {
+        let mut attempt = 0;
+        loop {
+            match self.prepare_schedule(cancellation_token).await {
+                Err(DispatcherError::DbError(e))
+                    if attempt < self.schedule_retry_attempts && is_connection_error(&e) =>
+                {
+                    attempt += 1;
+                    warn!(
+                        "prepare_schedule: connection error, retrying ({}/{}): {}",
+                        attempt, self.schedule_retry_attempts, e
+                    );
+                    self.stats.add_schedule_retry();
+                    tokio::select! {
+                        _ = cancellation_token.cancelled() => {
+                            return Err(DispatcherError::TerminatingSignalReceived)
+                        }
+                        _ = tokio::time::sleep(self.schedule_retry_backoff * attempt) => {}
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Moves the `Created` processes of the given (validated) sources to `Pending`,
+    /// one `UPDATE` per `SOURCE_IDS_CHUNK_SIZE` ids. Each chunk is retried on its
+    /// own after a lock conflict and counted once committed, so a failing later
+    /// chunk does not lose the earlier ones. Returns the number of promoted processes.
+    /// Nothing calls it yet: there is no validation step to promote after.
+    pub async fn promote_created_to_pending(&self, source_ids: &[u32]) -> Result<u64, sqlx::Error> {
+        let mut promoted_cnt = 0;
+        for chunk in source_ids.chunks(SOURCE_IDS_CHUNK_SIZE) {
+            let chunk_cnt = retry_on_lock_conflict(
+                "promote_created_to_pending",
+                || self.stats.add_lock_retry(),
+                || self.process_store.promote_created_to_pending(chunk),
+            )
+            .await?;
+            self.stats.add_promoted_processes(chunk_cnt);
+            promoted_cnt += chunk_cnt;
+        }
+        if promoted_cnt > 0 {
+            info!("{} created processes promoted to pending", promoted_cnt);
         }
+        Ok(promoted_cnt)
+    }
+
+    /// Runs exactly one schedule cycle to completion, without a shutdown signal.
+    /// Meant for tests that assert the DB state after a cycle.
+    pub async fn run_one_cycle(&self) -> Result<ScheduleReport, DispatcherError> {
+        self.prepare_schedule(&CancellationToken::new()).await
+    }
+
+    /// Ready when both DB pools answer the ping and, with `READY_REQUIRES_SCHEDULE`,
+    /// at least one schedule cycle has completed.
+    pub async fn readiness(&self) -> Readiness {
+        let db = match self.process_store.ping().await {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Readiness DB ping failed: {}", e);
+                false
+            }
+        };
+        let schedule = !self.ready_requires_schedule
+            || self.schedule_last_success.load(Ordering::Relaxed) != 0;
+        Readiness { db, schedule }
+    }
+
+    /// Pings every DB pool on its own; `true` per pool name if it answered.
+    /// Gives up when the token is cancelled, so a probe cannot delay shutdown.
+    pub async fn pool_health(
+        &self,
+        cancellation_token: &CancellationToken,
+    ) -> Result<BTreeMap<&'static str, bool>, CancellationError> {
+        let pings = async { Ok::<_, CancellationError>(self.process_store.ping_each().await) }
+            .with_cancellation::<CancellationError>(cancellation_token, "pool_health:ping")
+            .await?;
+        Ok(pings
+            .into_iter()
+            .map(|(pool, ping)| {
+                if let Err(e) = &ping {
+                    warn!("Health check ping of the {} pool failed: {}", pool, e);
+                }
+                (pool, ping.is_ok())
+            })
+            .collect())
+    }
+
+    /// Drops the cached active source ids and reloads them from `sources`.
+    /// Returns the number of active source ids.
+    pub async fn refresh_source_ids(&self) -> Result<usize, sqlx::Error> {
+        self.source_ids_cache.invalidate();
+        let source_ids = Arc::new(self.fetch_active_source_ids().await?);
+        let cnt = source_ids.len();
+        self.source_ids_cache.set(source_ids);
+        info!("Active source ids refreshed: {}", cnt);
+        Ok(cnt)
+    }
+
+    async fn fetch_active_source_ids(&self) -> Result<Vec<u32>, sqlx::Error> {
+        //requesting a stream (sending a request to DB without waiting for the response)
+        let source_ids_stream = self.process_store.available_source_ids_stream().await?;
 
-        Ok(created_cnt)
+        //fetching result rows from the stream
+        source_ids_stream.try_collect().await
     }
 
+    /// Creates a process for a source picked by `sources_needing_new_process`,
+    /// re-checking its latest process in case it changed since the batch read.
+    ///
+    /// The check-then-insert runs under the per-source lock, so within one instance
+    /// only one task can create a process for a given source at a time.
     async fn process_source(
         &self,
         source_id: u32,
         cancellation_token: &CancellationToken,
     ) -> Result<u16, DispatcherError> {
+        let source_lock = self.source_locks.get_mutex(source_id);
+        let _source_guard = source_lock.lock().await;
+
+        //a regular process never runs next to an unfinished sandbox one
+        if self
+            .process_store
+            .has_unfinished_process(source_id, ProcessingMode::Sandbox)
+            .with_cancellation::<DispatcherError>(
+                cancellation_token,
+                "process_source:has_unfinished_sandbox",
+            )
+            .await?
+        {
+            trace!(
+                "There is an unfinished sandbox process for source id: {}",
+                source_id
+            );
+            return Ok(0);
+        }
+
         //searching for potential not finished processes
         let process = self
-            .db_repository
+            .process_store
             .get_latest_process_for(source_id)
             .with_cancellation::<DispatcherError>(
                 cancellation_token,
                 "process_source:get_latest_process",
             )
             .await?;
-        if process.is_some() {
-            let process = process.unwrap();
-            let state = DispatchState::new(&process.get_string("state"));
+        if let Some(process) = process {
+            let state = process.dispatch_state()?;
 
-            //not: Completed, Failed
-            if !state.is_finished() {
+            //not: Completed, Failed (nor Error with ERROR_IS_TERMINAL)
+            if !self.is_finished_for_scheduling(&state) {
                 trace!(
                     "There is already present process in state {} for source id: {}",
                     state,
@@ -134,9 +584,18 @@ impl Dispatcher {
                 return Ok(0);
             }
 
-            let created_at =
-                DispatchTimeFormatter::db_to_dt(&process.get_string("created_at"), None);
-            let now = DispatchTimeFormatter::now_dt();
+            let Some(created_at) = &process.created_at else {
+                warn!(
+                    "Latest process {} of source id {} has no created_at",
+                    process.uuid, source_id
+                );
+                if self.null_created_at == NullCreatedAt::Skip {
+                    return Ok(0);
+                }
+                return self.create_scheduled_process(source_id, cancellation_token).await;
+            };
+            let created_at = self.time_formatter.db_to_dt(created_at, None);
+            let now = self.time_formatter.now_dt();
 
             if now.date_naive() == created_at.date_naive() {
                 trace!(
@@ -147,8 +606,16 @@ impl Dispatcher {
             }
         }
 
+        self.create_scheduled_process(source_id, cancellation_token).await
+    }
+
+    async fn create_scheduled_process(
+        &self,
+        source_id: u32,
+        cancellation_token: &CancellationToken,
+    ) -> Result<u16, DispatcherError> {
         let uuid = self
-            .db_repository
+            .process_store
             .insert_new_process(source_id, DispatchState::Created, ProcessingMode::Regular)
             .with_cancellation::<DispatcherError>(
                 cancellation_token,
@@ -160,37 +627,161 @@ impl Dispatcher {
             "A new regular process {} for source id: {} has been created",
             uuid, source_id
         );
+        if self.scan_strategy == ScanStrategy::Recency {
+            self.source_recency.bump(source_id);
+        }
+        self.post_commit_hooks.run(&ProcessEvent::Created {
+            process_id: uuid,
+            source_id,
+        });
         Ok(1)
     }
 
     pub async fn assign_process(
         &self,
         supervisor_id: Uuid,
-    ) -> Result<Option<AssignedProcess>, sqlx::Error> {
+    ) -> Result<AssignOutcome, DispatcherError> {
+        self.assign_process_excluding(supervisor_id, &HashSet::new())
+            .await
+    }
+
+    /// Up to `count` `assign_process` calls for one supervisor, stopping at the
+    /// first one without work. Sources already assigned in the batch are skipped,
+    /// so a batch never takes two processes of the same source. An error after
+    /// the first claim ends the batch instead of failing it, so the processes
+    /// already claimed are still returned to the supervisor.
+    pub async fn assign_processes(
+        &self,
+        supervisor_id: Uuid,
+        count: u32,
+    ) -> Result<AssignBatch, DispatcherError> {
+        let mut batch = AssignBatch::default();
+        let mut assigned_sources = HashSet::new();
+        while batch.processes.len() < count as usize {
+            match self
+                .assign_process_excluding(supervisor_id, &assigned_sources)
+                .await
+            {
+                Ok(AssignOutcome::Assigned(process)) => {
+                    assigned_sources.insert(process.source_id);
+                    batch.processes.push(process);
+                }
+                Ok(AssignOutcome::NoWork(reason)) => {
+                    batch.stop_reason = Some(BatchStopReason::NoWork(reason));
+                    break;
+                }
+                Err(e) if batch.processes.is_empty() => return Err(e),
+                Err(e) => {
+                    error!(
+                        "Batch for supervisor {} stopped after {} processes: {}",
+                        supervisor_id,
+                        batch.processes.len(),
+                        e
+                    );
+                    batch.stop_reason = Some(BatchStopReason::Error);
+                    break;
+                }
+            }
+        }
+        Ok(batch)
+    }
+
+    async fn assign_process_excluding(
+        &self,
+        supervisor_id: Uuid,
+        exclude_sources: &HashSet<u32>,
+    ) -> Result<AssignOutcome, DispatcherError> {
+        if self.drain.is_draining() {
+            info!("Draining, no process is assigned");
+            self.stats.add_assign_no_work();
+            return Ok(AssignOutcome::NoWork(NoWorkReason::Draining));
+        }
+        let mut probes = 0;
+        let result = self
+            .probe_candidates(supervisor_id, exclude_sources, &mut probes)
+            .await;
+        self.stats.add_assign_probes(probes);
+        if let Ok(AssignOutcome::NoWork(_)) = result {
+            self.stats.add_assign_no_work();
+        }
+        result
+    }
+
+    /// Tries candidates oldest first, at most `ASSIGN_MAX_PROBES` of them;
+    /// `probes` counts the tried candidates (sources). Candidates of
+    /// `exclude_sources` are passed over without counting as a probe.
+    async fn probe_candidates(
+        &self,
+        supervisor_id: Uuid,
+        exclude_sources: &HashSet<u32>,
+        probes: &mut u64,
+    ) -> Result<AssignOutcome, DispatcherError> {
         info!("Searching for process to assigning...");
-        //get list of source ids that have active processes in DB
-        let mut sources_stream = self
-            .db_repository
-            .get_available_processes_sources_stream(supervisor_id, 10)
+        //get the oldest assignable processes in DB, one extra to tell a hit cap from no work
+        let limit = self
+            .assign_max_probes
+            .saturating_add(1)
+            .saturating_add(exclude_sources.len() as u32);
+        let mut candidates_stream = self
+            .process_store
+            .get_available_processes_sources_stream(supervisor_id, limit)
             .await?;
 
-        loop {
-            let row_option = sources_stream.try_next().await?;
-            if row_option.is_none() {
-                info!("No available source ids found for assigning.");
-                return Ok(None);
+        while let Some(candidate) = candidates_stream.try_next().await? {
+            if exclude_sources.contains(&candidate.source_id) {
+                continue;
+            }
+            if *probes >= u64::from(self.assign_max_probes) {
+                //every probe was skipped: persistent hits point to a livelock, not to load
+                warn!(
+                    "ASSIGN_MAX_PROBES ({}) reached without a claim",
+                    self.assign_max_probes
+                );
+                self.stats.add_assign_probe_cap_hit();
+                return Ok(AssignOutcome::NoWork(NoWorkReason::ProbeCapReached));
             }
-            let process_row = row_option.unwrap();
-            let source_id: u32 = process_row
-                .try_get("source_id")
-                .expect("unexpected source id result");
+            *probes += 1;
+            let source_id = candidate.source_id;
 
-            //lock any DB operations while we process with the current source
-            let lock = self.source_locks.get_mutex(source_id);
+            //serialize with the scheduler and other assignments of the same source
+            let source_lock = self.source_locks.get_mutex(source_id);
+            let source_guard = source_lock.lock().await;
+            //decoded before the claim, an unknown value must not leave the row taken
+            let state = candidate.dispatch_state()?;
+            let processing_mode = candidate.processing_mode()?;
 
-            //get available processes for the current source
+            //common case: the candidate is still free, claim it without another lookup
+            let rows_affected = retry_on_lock_conflict(
+                "assign_process:claim_candidate",
+                || self.stats.add_lock_retry(),
+                || {
+                    self.process_store.claim_candidate(
+                        candidate.uuid,
+                        supervisor_id,
+                        DispatchState::Processing,
+                    )
+                },
+            )
+            .await?;
+            if rows_affected > 0 {
+                info!(
+                    "Assigned process {} for source id: {} with state: {} and processing type: {}",
+                    candidate.uuid, source_id, state, processing_mode
+                );
+                let created_at =
+                    self.time_formatter.db_to_dt_or_oldest(candidate.created_at.as_deref());
+                return Ok(AssignOutcome::Assigned(self.assigned(
+                    candidate.uuid,
+                    source_id,
+                    processing_mode,
+                    created_at,
+                    supervisor_id,
+                )));
+            }
+
+            //the candidate was taken in the meantime, fall back to other processes of the source
             let mut processes_stream = self
-                .db_repository
+                .process_store
                 .get_available_source_processes_stream(source_id, 1)
                 .await?;
 
@@ -201,112 +792,1634 @@ impl Dispatcher {
                         "No available processes found for source {} to assigning.",
                         source_id
                     );
-                    drop(lock);
+                    drop(source_guard);
                     break;
                 }
                 //we have a new non-assigned process
-                let process_row = row_option.unwrap();
-                let process_id: Uuid = process_row.try_get("uuid").expect("unexpected uuid result");
-                let supervisor_id_option: Option<Vec<u8>> = process_row
-                    .try_get("supervisor_id")
-                    .expect("unexpected supervisor id result");
-                let state = DispatchState::new(&process_row.get_string("state"));
-                let processing_mode: u8 = process_row
-                    .try_get("mode")
-                    .expect("Unexpected 'mode' result value from DB");
-                let processing_mode = ProcessingMode::new(processing_mode as isize);
-                let created_at_string = process_row.get_string("created_at");
-                let created_at = DispatchTimeFormatter::db_to_dt(&created_at_string, Some(UTC));
+                let process = row_option.unwrap();
+                let process_id = process.uuid;
+                let state = process.dispatch_state()?;
+                let processing_mode = process.processing_mode()?;
+                let created_at =
+                    self.time_formatter.db_to_dt_or_oldest(process.created_at.as_deref());
 
                 //we should get only active and unassigned process
-                if !state.is_finished() && supervisor_id_option.is_none() {
+                if !state.is_finished() && process.supervisor_id.is_none() {
                     info!(
                         "Assigning process {} for source id: {} with state: {} and processing type: {} in DB...",
                         process_id, source_id, state, processing_mode
                     );
-                    let new_state = DispatchState::Processing;
-                    self.db_repository
-                        .assign_process_to_supervisor(process_id, supervisor_id, new_state.clone())
-                        .await?;
-
-                    let assigned_process = AssignedProcess::new(
-                        process_id.into(),
+                    let rows_affected = retry_on_lock_conflict(
+                        "assign_process:try_claim_process",
+                        || self.stats.add_lock_retry(),
+                        || {
+                            self.process_store.try_claim_process(
+                                process_id,
+                                supervisor_id,
+                                DispatchState::Processing,
+                            )
+                        },
+                    )
+                    .await?;
+                    if rows_affected == 0 {
+                        //another supervisor claimed it since the read, try the next one
+                        info!("Process {} was claimed by another supervisor.", process_id);
+                        continue;
+                    }
+                    return Ok(AssignOutcome::Assigned(self.assigned(
+                        process_id,
                         source_id,
-                        new_state,
                         processing_mode,
-                        created_at.to_utc(),
-                        supervisor_id.into(),
-                    );
-                    return Ok(Some(assigned_process));
+                        created_at,
+                        supervisor_id,
+                    )));
                 }
             }
         }
+
+        if *probes == 0 {
+            info!("No available source ids found for assigning.");
+            return Ok(AssignOutcome::NoWork(NoWorkReason::NoCandidates));
+        }
+        info!("All {} candidates were taken by other supervisors.", probes);
+        Ok(AssignOutcome::NoWork(NoWorkReason::AllTaken))
     }
 
-    pub async fn report_process_finish(
+    /// `Error` counts as finished with `ERROR_IS_TERMINAL`: it is never reassigned
+    /// and does not block new processes of its source.
+    fn is_finished_for_scheduling(&self, state: &DispatchState) -> bool {
+        state.is_finished() || (self.error_is_terminal && *state == DispatchState::Error)
+    }
+
+    /// Runs the post-commit hooks of a committed assignment.
+    fn assigned(
         &self,
         process_id: Uuid,
-        result: &str,
-    ) -> Result<(), ReportFinishError> {
-        let new_state = match result {
-            REPORT_STATUS_SUCCESS => DispatchState::Completed,
-            REPORT_STATUS_ERROR => DispatchState::Failed,
-            other => return Err(ReportFinishError::InvalidResult(other.to_owned())),
-        };
+        source_id: u32,
+        processing_mode: ProcessingMode,
+        created_at: DateTime<Tz>,
+        supervisor_id: Uuid,
+    ) -> AssignedProcess {
+        self.recent_writes.record(process_id);
+        self.stats.add_process_assigned(processing_mode);
+        self.post_commit_hooks.run(&ProcessEvent::Assigned {
+            process_id,
+            source_id,
+            supervisor_id,
+        });
+        AssignedProcess::new(
+            process_id.into(),
+            source_id,
+            DispatchState::Processing,
+            processing_mode,
+            created_at.to_utc(),
+            supervisor_id.into(),
+        )
+    }
+
+    /// Streams all non-finished processes (capped by `EXPORT_MAX_ROWS`) without
+    /// buffering the result set.
+    pub async fn export_non_finished_processes(
+        &self,
+    ) -> Result<BoxStream<'static, Result<ExportedProcess, sqlx::Error>>, sqlx::Error> {
+        let processes = self
+            .process_store
+            .non_finished_processes_stream(self.export_max_rows)
+            .await?;
+        Ok(processes
+            .map_ok(|process| ExportedProcess {
+                uuid: process.uuid,
+                source_id: process.source_id,
+                state: process.state,
+                mode: process.mode,
+                created_at: process.created_at,
+                supervisor_id: process.supervisor_id,
+            })
+            .boxed())
+    }
+
+    /// Atomically claims the given process for the supervisor, if nobody else did.
+    pub async fn assign_process_by_id(
+        &self,
+        supervisor_id: Uuid,
+        process_id: Uuid,
+    ) -> Result<AssignedProcess, AssignByIdError> {
+        if self.drain.is_draining() {
+            return Err(AssignByIdError::Draining);
+        }
+        info!(%process_id, %supervisor_id, "Claiming process by id...");
+
+        let rows_affected = retry_on_lock_conflict(
+            "assign_process_by_id:claim_process_by_id",
+            || self.stats.add_lock_retry(),
+            || {
+                self.process_store.claim_process_by_id(
+                    process_id,
+                    supervisor_id,
+                    DispatchState::Processing,
+                )
+            },
+        )
+        .await
+        .map_err(AssignByIdError::Db)?;
+
+        let process = self
+            .process_store
+            .get_process(process_id)
+            .await
+            .map_err(AssignByIdError::Db)?
+            .ok_or(AssignByIdError::NotFound(process_id))?;
+        if rows_affected == 0 {
+            return Err(AssignByIdError::NotClaimable(process_id));
+        }
+
+        let processing_mode = process
+            .processing_mode()
+            .map_err(|e| AssignByIdError::InvalidState(e.to_string()))?;
+        let created_at = self.time_formatter.db_to_dt_or_oldest(process.created_at.as_deref());
+        Ok(self.assigned(
+            process_id,
+            process.source_id,
+            processing_mode,
+            created_at,
+            supervisor_id,
+        ))
+    }
 
+    /// Unassigns `Processing` rows left over from a previous run of a supervisor
+    /// that restarted with the same id. Returns the number of reset processes.
+    pub async fn reset_supervisor(&self, supervisor_id: Uuid) -> Result<u64, sqlx::Error> {
+        let reset_cnt = retry_on_lock_conflict(
+            "reset_supervisor:reset_supervisor_processes",
+            || self.stats.add_lock_retry(),
+            || self.process_store.reset_supervisor_processes(supervisor_id),
+        )
+        .await?;
         info!(
-            %process_id,
-            state = %new_state,
-            "Reporting process finish..."
+            %supervisor_id,
+            "{} stale processing processes reset to created", reset_cnt
         );
+        Ok(reset_cnt)
+    }
 
-        let rows_affected = self
-            .db_repository
-            .update_process_state(process_id, new_state)
+    /// Unassigns a not finished process and puts it back as `Created`, so any
+    /// supervisor can claim it again. Returns `false` for an unknown or finished
+    /// process (`Error` too with `ERROR_IS_TERMINAL`) and for a queued process no
+    /// supervisor holds.
+    pub async fn release_process(&self, process_id: Uuid) -> Result<bool, sqlx::Error> {
+        let released_cnt = retry_on_lock_conflict(
+            "release_process:release_process",
+            || self.stats.add_lock_retry(),
+            || self.process_store.release_process(process_id),
+        )
+        .await?;
+        if released_cnt == 0 {
+            return Ok(false);
+        }
+        self.recent_writes.record(process_id);
+        info!(%process_id, "Process released back to the queue");
+        Ok(true)
+    }
+
+    /// Inserts a new `Created` process of the given mode for a source, e.g. a
+    /// `Sandbox` one for testing. Unlike the scheduler, it does not check for a
+    /// not finished process or a process of today first.
+    pub async fn create_process(
+        &self,
+        source_id: u32,
+        mode: ProcessingMode,
+    ) -> Result<Uuid, CreateProcessError> {
+        let source_lock = self.source_locks.get_mutex(source_id);
+        let _source_guard = source_lock.lock().await;
+        if let Some(other_mode) = self
+            .conflicting_mode(source_id, mode)
+            .await
+            .map_err(CreateProcessError::Db)?
+        {
+            return Err(CreateProcessError::ModeConflict(source_id, other_mode));
+        }
+        let process_id = self
+            .process_store
+            .insert_new_process(source_id, DispatchState::Created, mode)
             .await
-            .map_err(ReportFinishError::Db)?;
+            .map_err(CreateProcessError::Db)?;
+        info!(
+            %process_id,
+            source_id,
+            %mode,
+            "Manual create: a new process has been created"
+        );
+        self.post_commit_hooks.run(&ProcessEvent::Created {
+            process_id,
+            source_id,
+        });
+        Ok(process_id)
+    }
 
-        if rows_affected == 0 {
-            return Err(ReportFinishError::NotFound(process_id));
+    /// Inserts a new `Created` process with the source and mode of a finished one,
+    /// bypassing the same-day guard of the scheduler. The original row is untouched.
+    pub async fn rerun_process(&self, process_id: Uuid) -> Result<Uuid, RerunError> {
+        //the source of a process never changes, everything else is checked under the lock
+        let source_id = self.process_for_rerun(process_id).await?.source_id;
+        let source_lock = self.source_locks.get_mutex(source_id);
+        let _source_guard = source_lock.lock().await;
+        let process = self.process_for_rerun(process_id).await?;
+        let state = process
+            .dispatch_state()
+            .map_err(|e| RerunError::InvalidState(e.to_string()))?;
+        if !self.is_finished_for_scheduling(&state) {
+            return Err(RerunError::NotFinished(process_id, state));
         }
-        Ok(())
+        let processing_mode = process
+            .processing_mode()
+            .map_err(|e| RerunError::InvalidState(e.to_string()))?;
+        //a second rerun, or the scheduler, may have queued the source meanwhile
+        if self
+            .process_store
+            .has_unfinished_process(source_id, processing_mode)
+            .await
+            .map_err(RerunError::Db)?
+        {
+            return Err(RerunError::SourceBusy(source_id, processing_mode));
+        }
+        if let Some(other_mode) = self
+            .conflicting_mode(source_id, processing_mode)
+            .await
+            .map_err(RerunError::Db)?
+        {
+            return Err(RerunError::ModeConflict(source_id, other_mode));
+        }
+
+        let new_process_id = self
+            .process_store
+            .insert_new_process(source_id, DispatchState::Created, processing_mode)
+            .await
+            .map_err(RerunError::Db)?;
+        warn!(
+            %process_id,
+            %new_process_id,
+            source_id,
+            "Manual rerun: created a new process bypassing the same-day guard"
+        );
+        self.post_commit_hooks.run(&ProcessEvent::Created {
+            process_id: new_process_id,
+            source_id,
+        });
+        Ok(new_process_id)
     }
-}
 
-#[derive(Debug)]
-pub enum ReportFinishError {
-    InvalidResult(String),
-    NotFound(Uuid),
-    Db(sqlx::Error),
-}
+    async fn process_for_rerun(&self, process_id: Uuid) -> Result<ProcessRecord, RerunError> {
+        self.process_store
+            .get_process(process_id)
+            .await
+            .map_err(RerunError::Db)?
+            .ok_or(RerunError::NotFound(process_id))
+    }
 
-impl std::fmt::Display for ReportFinishError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ReportFinishError::InvalidResult(v) => write!(f, "invalid result value '{}'", v),
-            ReportFinishError::NotFound(id) => write!(f, "process {} not found", id),
-            ReportFinishError::Db(e) => write!(f, "db error: {}", e),
-        }
+    /// The other processing mode if the source has an unfinished process of it:
+    /// regular and sandbox processes of one source must never run side by side.
+    /// Called under the per-source lock.
+    async fn conflicting_mode(
+        &self,
+        source_id: u32,
+        mode: ProcessingMode,
+    ) -> Result<Option<ProcessingMode>, sqlx::Error> {
+        let other_mode = match mode {
+            ProcessingMode::Regular => ProcessingMode::Sandbox,
+            ProcessingMode::Sandbox => ProcessingMode::Regular,
+        };
+        Ok(self
+            .process_store
+            .has_unfinished_process(source_id, other_mode)
+            .await?
+            .then_some(other_mode))
     }
-}
 
-struct DispatchTimeFormatter;
+    /// Build version and DB versions, for `GET /info`.
+    pub async fn info(&self) -> Result<DispatcherInfo, sqlx::Error> {
+        Ok(DispatcherInfo::new(
+            env!("CARGO_PKG_VERSION").to_string(),
+            self.process_store.db_version().await?,
+            self.process_store.applied_migration_version().await?,
+        ))
+    }
 
-impl DispatchTimeFormatter {
-    pub fn db_to_dt(db_datetime: &str, timezone: Option<Tz>) -> DateTime<Tz> {
-        let datetime_format = "%Y-%m-%d %H:%M:%S%.f"; // Format for MySQL TIMESTAMP(3)
-        let created_at_utc = NaiveDateTime::parse_from_str(db_datetime, datetime_format)
-            .expect("Failed to parse datetime");
-        DateTime::<Utc>::from_naive_utc_and_offset(created_at_utc, Utc)
-            .with_timezone(&timezone.unwrap_or(Self::timezone()))
+    /// Current state of a process. Reads go to the read replica, if configured,
+    /// except for processes this instance wrote within `READ_YOUR_WRITES_SECS`.
+    pub async fn process_status(
+        &self,
+        process_id: Uuid,
+    ) -> Result<Option<ProcessStatus>, DispatcherError> {
+        let from_primary = self.recent_writes.contains(process_id);
+        let Some(process) = self
+            .process_store
+            .get_process_for_status(process_id, from_primary)
+            .await?
+        else {
+            return Ok(None);
+        };
+        self.process_status_of(process).map(Some)
     }
 
-    pub fn now_dt() -> DateTime<Tz> {
-        let utc_now = Utc::now();
-        utc_now.with_timezone(&Self::timezone())
+    /// Processes matching the filter, newest first, always read from the primary.
+    /// A row with a state or mode this build does not know is logged and left
+    /// out, so one bad row cannot fail the whole page.
+    pub async fn list_processes(
+        &self,
+        filter: &ProcessFilter,
+    ) -> Result<Vec<ProcessStatus>, DispatcherError> {
+        Ok(self
+            .process_store
+            .list_processes(filter)
+            .await?
+            .into_iter()
+            .filter_map(|process| {
+                let process_id = process.uuid;
+                self.process_status_of(process)
+                    .inspect_err(|e| warn!(%process_id, "Process left out of the list: {}", e))
+                    .ok()
+            })
+            .collect())
     }
 
-    fn timezone() -> Tz {
-        Tz::from_str(TIMEZONE).expect("invalid timezone")
+    fn process_status_of(&self, process: ProcessRecord) -> Result<ProcessStatus, DispatcherError> {
+        let processing_mode = process.processing_mode()?;
+        let created_at = self.time_formatter.db_to_dt_or_oldest(process.created_at.as_deref());
+        Ok(ProcessStatus::new(
+            process.uuid.into(),
+            process.source_id,
+            process.dispatch_state()?,
+            processing_mode,
+            created_at.to_utc(),
+            process.supervisor_id.map(String::from),
+            process.failure_reason,
+            self.process_classes.class_of(processing_mode).to_string(),
+        ))
+    }
+
+    /// Rank of a waiting (unassigned `Created` / `Pending`) process, oldest first.
+    /// Both positions are `None` for any other process.
+    pub async fn queue_position(
+        &self,
+        process_id: Uuid,
+    ) -> Result<Option<QueuePosition>, DispatcherError> {
+        let Some(process) = self.process_store.get_process(process_id).await? else {
+            return Ok(None);
+        };
+        let state = process.dispatch_state()?;
+        let is_waiting = matches!(state, DispatchState::Created | DispatchState::Pending)
+            && process.supervisor_id.is_none();
+        if !is_waiting {
+            return Ok(Some(QueuePosition::new(process_id.into(), None, None)));
+        }
+        //unknown age counts as the oldest, as in the assignment order
+        let Some(created_at) = &process.created_at else {
+            return Ok(Some(QueuePosition::new(process_id.into(), Some(0), Some(0))));
+        };
+        let source_position = self
+            .process_store
+            .count_queued_before(Some(process.source_id), created_at)
+            .await?;
+        let global_position = self
+            .process_store
+            .count_queued_before(None, created_at)
+            .await?;
+        Ok(Some(QueuePosition::new(
+            process_id.into(),
+            Some(source_position),
+            Some(global_position),
+        )))
+    }
+
+    pub async fn report_process_finish(
+        &self,
+        process_id: Uuid,
+        result: &str,
+    ) -> Result<(), ReportFinishError> {
+        let new_state = match result {
+            REPORT_STATUS_SUCCESS => DispatchState::Completed,
+            REPORT_STATUS_ERROR => DispatchState::Failed,
+            other => return Err(ReportFinishError::InvalidResult(other.to_owned())),
+        };
+        self.finish_process(process_id, new_state).await
+    }
+
+    /// Moves an assigned process to a finished state given in the DB spelling
+    /// (`completed` or `failed`).
+    pub async fn update_process_state(
+        &self,
+        process_id: Uuid,
+        state: &str,
+    ) -> Result<(), ReportFinishError> {
+        let new_state = DispatchState::try_from(state)
+            .ok()
+            .filter(DispatchState::is_finished)
+            .ok_or_else(|| ReportFinishError::NotFinishedState(state.to_owned()))?;
+        self.finish_process(process_id, new_state).await
+    }
+
+    async fn finish_process(
+        &self,
+        process_id: Uuid,
+        new_state: DispatchState,
+    ) -> Result<(), ReportFinishError> {
+        info!(
+            %process_id,
+            state = %new_state,
+            "Reporting process finish..."
+        );
+
+        let Some(process) = self
+            .process_store
+            .update_process_state(process_id, new_state.clone())
+            .await
+            .map_err(ReportFinishError::Db)?
+        else {
+            //a repeated report must not record the latency or run the hooks again
+            let finished_state = self
+                .process_store
+                .get_process(process_id)
+                .await
+                .map_err(ReportFinishError::Db)?
+                .and_then(|process| process.dispatch_state().ok())
+                .filter(DispatchState::is_finished);
+            return Err(match finished_state {
+                Some(state) => ReportFinishError::AlreadyFinished(process_id, state),
+                None => ReportFinishError::NotFound(process_id),
+            });
+        };
+
+        self.recent_writes.record(process_id);
+        self.post_commit_hooks.run(&ProcessEvent::Finished {
+            process_id,
+            source_id: process.source_id,
+            supervisor_id: process.supervisor_id,
+            state: new_state.clone(),
+        });
+        self.record_finish(&process);
+        Ok(())
+    }
+
+    /// Records the latency of a finished process, using `updated_at` of the just
+    /// finished row as its finish time.
+    fn record_finish(&self, process: &ProcessRecord) {
+        let source_id = process.source_id;
+        //a process of unknown age has no meaningful latency
+        let Some(created_at) = &process.created_at else {
+            return;
+        };
+        let created_at = self.time_formatter.db_to_dt(created_at, Some(UTC));
+        let finished_at = self.time_formatter.db_to_dt(&process.updated_at, Some(UTC));
+        let latency = (finished_at - created_at).to_std().unwrap_or_default();
+        self.source_latencies.record(source_id, latency);
+    }
+}
+
+/// Outcome of one `prepare_schedule` cycle.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScheduleReport {
+    /// Active source ids considered.
+    pub sources_scanned: usize,
+    pub processes_created: u16,
+    /// Sources that already have an unfinished or today's process.
+    pub sources_skipped: usize,
+    /// Sources left for the next cycles by `MAX_CREATES_PER_CYCLE`.
+    pub sources_deferred: usize,
+    /// Sources whose processing failed with an error (logged).
+    pub sources_failed: usize,
+}
+
+impl ScheduleReport {
+    /// Nothing was created, the driving loop may back off.
+    pub fn is_idle(&self) -> bool {
+        self.processes_created == 0
+    }
+}
+
+#[derive(Debug)]
+pub enum AssignOutcome {
+    Assigned(AssignedProcess),
+    NoWork(NoWorkReason),
+}
+
+/// Result of `assign_processes`: the assigned processes, oldest first, and why
+/// the batch stopped early (`None` if `count` processes were assigned).
+#[derive(Debug, Default)]
+pub struct AssignBatch {
+    pub processes: Vec<AssignedProcess>,
+    pub stop_reason: Option<BatchStopReason>,
+}
+
+/// Why `assign_processes` stopped before `count`, reported in `X-Assign-Reason`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchStopReason {
+    NoWork(NoWorkReason),
+    /// A claim failed after at least one process was assigned.
+    Error,
+}
+
+impl BatchStopReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BatchStopReason::NoWork(reason) => reason.as_str(),
+            BatchStopReason::Error => ASSIGN_REASON_ERROR,
+        }
+    }
+}
+
+/// Why `assign_process` found nothing, reported in the `X-Assign-Reason` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoWorkReason {
+    /// No assignable process in DB.
+    NoCandidates,
+    /// Every candidate was claimed by another supervisor in the meantime.
+    AllTaken,
+    /// `ASSIGN_MAX_PROBES` candidates were tried without success.
+    ProbeCapReached,
+    Draining,
+}
+
+impl NoWorkReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoWorkReason::NoCandidates => ASSIGN_REASON_NO_SOURCES,
+            NoWorkReason::AllTaken => ASSIGN_REASON_ALL_TAKEN,
+            NoWorkReason::ProbeCapReached => ASSIGN_REASON_PROBE_CAP_REACHED,
+            NoWorkReason::Draining => ASSIGN_REASON_DRAINING,
+        }
+    }
+}
+
+/// What `prepare_schedule` does with a source whose latest finished process has
+/// a NULL `created_at`, so the same-day guard cannot be evaluated (`NULL_CREATED_AT`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullCreatedAt {
+    /// Create a new process, as if the old one was from another day.
+    Create,
+    /// Leave the source alone until the row is fixed.
+    Skip,
+}
+
+impl FromStr for NullCreatedAt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "create" => Ok(NullCreatedAt::Create),
+            "skip" => Ok(NullCreatedAt::Skip),
+            other => Err(format!("unknown NULL created_at handling '{}'", other)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ReportFinishError {
+    InvalidResult(String),
+    /// Not `completed`/`failed`.
+    NotFinishedState(String),
+    /// Unknown uuid, or the process is not `Processing` for a supervisor.
+    NotFound(Uuid),
+    /// The process was already finished, e.g. by a retried report.
+    AlreadyFinished(Uuid, DispatchState),
+    Db(sqlx::Error),
+}
+
+impl std::fmt::Display for ReportFinishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReportFinishError::InvalidResult(v) => write!(f, "invalid result value '{}'", v),
+            ReportFinishError::NotFinishedState(v) => {
+                write!(f, "invalid state value '{}', expected completed or failed", v)
+            }
+            ReportFinishError::NotFound(id) => write!(f, "process {} not found", id),
+            ReportFinishError::AlreadyFinished(id, state) => {
+                write!(f, "process {} is already finished as {}", id, state)
+            }
+            ReportFinishError::Db(e) => write!(f, "db error: {}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RerunError {
+    NotFound(Uuid),
+    NotFinished(Uuid, DispatchState),
+    /// The source already has an unfinished process of the same mode.
+    SourceBusy(u32, ProcessingMode),
+    /// The source has an unfinished process of the given other mode.
+    ModeConflict(u32, ProcessingMode),
+    InvalidState(String),
+    Db(sqlx::Error),
+}
+
+impl std::fmt::Display for RerunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RerunError::NotFound(id) => write!(f, "process {} not found", id),
+            RerunError::NotFinished(id, state) => {
+                write!(f, "process {} is not finished (state {})", id, state)
+            }
+            RerunError::SourceBusy(source_id, mode) => {
+                write!(f, "source {} already has an unfinished {} process", source_id, mode)
+            }
+            RerunError::ModeConflict(source_id, mode) => {
+                write!(f, "source {} has an unfinished {} process", source_id, mode)
+            }
+            RerunError::InvalidState(e) => write!(f, "{}", e),
+            RerunError::Db(e) => write!(f, "db error: {}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CreateProcessError {
+    /// The source has an unfinished process of the given other mode.
+    ModeConflict(u32, ProcessingMode),
+    Db(sqlx::Error),
+}
+
+impl std::fmt::Display for CreateProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CreateProcessError::ModeConflict(source_id, mode) => {
+                write!(f, "source {} has an unfinished {} process", source_id, mode)
+            }
+            CreateProcessError::Db(e) => write!(f, "db error: {}", e),
+        }
+    }
+}
+
+/// A `dispatcher_processes` row as exported by `GET /export.csv`.
+#[derive(Debug)]
+pub struct ExportedProcess {
+    pub uuid: Uuid,
+    pub source_id: u32,
+    pub state: String,
+    pub mode: u8,
+    /// `None` for a NULL `created_at`, exported as an empty field.
+    pub created_at: Option<String>,
+    pub supervisor_id: Option<Uuid>,
+}
+
+#[derive(Debug)]
+pub struct Readiness {
+    pub db: bool,
+    pub schedule: bool,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.db && self.schedule
+    }
+}
+
+#[derive(Debug)]
+pub enum AssignByIdError {
+    NotFound(Uuid),
+    NotClaimable(Uuid),
+    Draining,
+    InvalidState(String),
+    Db(sqlx::Error),
+}
+
+impl std::fmt::Display for AssignByIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssignByIdError::NotFound(id) => write!(f, "process {} not found", id),
+            AssignByIdError::NotClaimable(id) => {
+                write!(f, "process {} is already assigned or finished", id)
+            }
+            AssignByIdError::Draining => write!(f, "dispatcher is draining"),
+            AssignByIdError::InvalidState(e) => write!(f, "{}", e),
+            AssignByIdError::Db(e) => write!(f, "db error: {}", e),
+        }
+    }
+}
+
+/// Sorts source ids ascending, starting with the first id after `cursor`
+/// and wrapping around to the ones up to it.
+fn resume_order(mut source_ids: Vec<u32>, cursor: u32) -> Vec<u32> {
+    source_ids.sort_unstable();
+    let split = source_ids.partition_point(|&source_id| source_id <= cursor);
+    source_ids.rotate_left(split);
+    source_ids
+}
+
+/// Converts DB timestamps (UTC) to `DISPATCH_TIMEZONE`, the timezone of the
+/// calendar day the scheduler creates at most one process per source for.
+struct DispatchTimeFormatter {
+    timezone: Tz,
+}
+
+impl DispatchTimeFormatter {
+    fn new(timezone: Tz) -> Self {
+        DispatchTimeFormatter { timezone }
+    }
+
+    pub fn db_to_dt(&self, db_datetime: &str, timezone: Option<Tz>) -> DateTime<Tz> {
+        let datetime_format = "%Y-%m-%d %H:%M:%S%.f"; // Format for MySQL TIMESTAMP(3)
+        let created_at_utc = NaiveDateTime::parse_from_str(db_datetime, datetime_format)
+            .expect("Failed to parse datetime");
+        DateTime::<Utc>::from_naive_utc_and_offset(created_at_utc, Utc)
+            .with_timezone(&timezone.unwrap_or(self.timezone))
+    }
+
+    /// `db_to_dt` in UTC; a NULL `created_at` (unknown age) counts as the oldest
+    /// possible, the Unix epoch, like its position in `ORDER BY created_at ASC`.
+    pub fn db_to_dt_or_oldest(&self, db_datetime: Option<&str>) -> DateTime<Tz> {
+        match db_datetime {
+            Some(db_datetime) => self.db_to_dt(db_datetime, Some(UTC)),
+            None => DateTime::<Utc>::UNIX_EPOCH.with_timezone(&UTC),
+        }
+    }
+
+    pub fn now_dt(&self) -> DateTime<Tz> {
+        let utc_now = Utc::now();
+        utc_now.with_timezone(&self.timezone)
+    }
+
+    /// Midnight of the current day in `DISPATCH_TIMEZONE`.
+    pub fn today_start_dt(&self) -> DateTime<Tz> {
+        Self::day_start_dt(self.now_dt())
+    }
+
+    fn day_start_dt(dt: DateTime<Tz>) -> DateTime<Tz> {
+        dt.date_naive()
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(dt.timezone())
+            .earliest()
+            .unwrap_or(dt)
+    }
+
+    /// Inverse of `db_to_dt`: UTC in the MySQL `TIMESTAMP(3)` format.
+    pub fn dt_to_db(dt: DateTime<Tz>) -> String {
+        dt.with_timezone(&Utc)
+            .format("%Y-%m-%d %H:%M:%S%.3f")
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process_store::in_memory::InMemoryProcessStore;
+    use std::sync::atomic::AtomicUsize;
+
+    fn in_memory_dispatcher(source_ids: Vec<u32>) -> Dispatcher {
+        Dispatcher::new(
+            &EnvParams::for_tests(),
+            Arc::new(InMemoryProcessStore::new(source_ids)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_schedule_creates_one_process_per_source() {
+        let dispatcher = in_memory_dispatcher(vec![1, 2]);
+        let cancellation_token = CancellationToken::new();
+
+        let first = dispatcher.prepare_schedule(&cancellation_token).await.unwrap();
+        let second = dispatcher.prepare_schedule(&cancellation_token).await.unwrap();
+
+        assert_eq!(first.processes_created, 2);
+        assert_eq!(second.processes_created, 0);
+    }
+
+    #[tokio::test]
+    async fn test_promote_created_to_pending_of_given_sources() {
+        let dispatcher = in_memory_dispatcher(vec![1, 2, 3]);
+        dispatcher.run_one_cycle().await.unwrap();
+
+        let promoted_cnt = dispatcher.promote_created_to_pending(&[1, 3, 4]).await.unwrap();
+        let repromoted_cnt = dispatcher.promote_created_to_pending(&[1, 3]).await.unwrap();
+
+        assert_eq!(promoted_cnt, 2);
+        assert_eq!(repromoted_cnt, 0);
+        assert_eq!(dispatcher.stats().promoted_processes, 2);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_retries_connection_errors_only_up_to_the_limit() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        let cancellation_token = CancellationToken::new();
+
+        store.fail_source_ids_lookups(2);
+        let report = dispatcher
+            .prepare_schedule_with_retry(&cancellation_token)
+            .await
+            .unwrap();
+        assert_eq!(report.processes_created, 1);
+        assert_eq!(dispatcher.stats().schedule_retries, 2);
+
+        store.fail_source_ids_lookups(4);
+        let result = dispatcher.prepare_schedule_with_retry(&cancellation_token).await;
+        assert!(matches!(
+            result,
+            Err(DispatcherError::DbError(sqlx::Error::PoolTimedOut))
+        ));
+        assert_eq!(dispatcher.stats().schedule_retries, 5);
+    }
+
+    #[tokio::test]
+    async fn test_run_one_cycle_reports_counts() {
+        let dispatcher = Dispatcher::new(
+            &EnvParams::for_tests().with_max_creates_per_cycle(2),
+            Arc::new(InMemoryProcessStore::new(vec![1, 2, 3, 4])),
+        );
+
+        let first = dispatcher.run_one_cycle().await.unwrap();
+        let second = dispatcher.run_one_cycle().await.unwrap();
+        let third = dispatcher.run_one_cycle().await.unwrap();
+
+        assert_eq!(
+            first,
+            ScheduleReport {
+                sources_scanned: 4,
+                processes_created: 2,
+                sources_skipped: 0,
+                sources_deferred: 2,
+                sources_failed: 0,
+            }
+        );
+        assert_eq!((second.processes_created, second.sources_skipped), (2, 2));
+        assert!(third.is_idle());
+        assert_eq!(third.sources_skipped, 4);
+    }
+
+    #[tokio::test]
+    async fn test_capped_recency_cycles_keep_the_score_then_id_order() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1, 2, 3, 4]));
+        let dispatcher = Dispatcher::new(
+            &EnvParams::for_tests()
+                .with_max_creates_per_cycle(1)
+                .with_scan_strategy(ScanStrategy::Recency),
+            store.clone(),
+        );
+        dispatcher.source_recency.bump(4);
+        dispatcher.source_recency.bump(4);
+        dispatcher.source_recency.bump(2);
+
+        let mut created_order = Vec::new();
+        for _ in 0..4 {
+            dispatcher.run_one_cycle().await.unwrap();
+            for source_id in 1..=4 {
+                if !created_order.contains(&source_id)
+                    && store.get_latest_process_for(source_id).await.unwrap().is_some()
+                {
+                    created_order.push(source_id);
+                }
+            }
+        }
+
+        assert_eq!(created_order, vec![4, 2, 1, 3]);
+        assert_eq!(dispatcher.schedule_cursor.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_probe_cap_hit_is_counted() {
+        let dispatcher = Dispatcher::new(
+            &EnvParams::for_tests().with_assign_max_probes(0),
+            Arc::new(InMemoryProcessStore::new(vec![1])),
+        );
+        dispatcher.run_one_cycle().await.unwrap();
+
+        let outcome = dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+
+        assert!(matches!(
+            outcome,
+            AssignOutcome::NoWork(NoWorkReason::ProbeCapReached)
+        ));
+        assert_eq!(dispatcher.stats().assign_probe_cap_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_assign_process_claims_each_process_once() {
+        let dispatcher = in_memory_dispatcher(vec![1]);
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+
+        let assigned = dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+        let none_left = dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+
+        assert!(matches!(assigned, AssignOutcome::Assigned(process) if process.source_id == 1));
+        assert!(matches!(
+            none_left,
+            AssignOutcome::NoWork(NoWorkReason::NoCandidates)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_batch_takes_at_most_one_process_per_source() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1, 2, 3]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        store
+            .insert_new_process(1, DispatchState::Created, ProcessingMode::Regular)
+            .await
+            .unwrap();
+        let supervisor_id = Uuid::new_v4();
+
+        let first = dispatcher.assign_processes(supervisor_id, 10).await.unwrap();
+        let second = dispatcher.assign_processes(supervisor_id, 10).await.unwrap();
+        let third = dispatcher.assign_processes(supervisor_id, 10).await.unwrap();
+
+        let mut sources: Vec<u32> = first.processes.iter().map(|p| p.source_id).collect();
+        sources.sort_unstable();
+        assert_eq!(sources, vec![1, 2, 3]);
+        assert_eq!(
+            first.stop_reason,
+            Some(BatchStopReason::NoWork(NoWorkReason::NoCandidates))
+        );
+        assert_eq!(second.processes.len(), 1);
+        assert_eq!(second.processes[0].source_id, 1);
+        assert!(third.processes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_batch_stops_at_count() {
+        let dispatcher = in_memory_dispatcher(vec![1, 2, 3]);
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+
+        let batch = dispatcher.assign_processes(Uuid::new_v4(), 2).await.unwrap();
+
+        assert_eq!(batch.processes.len(), 2);
+        assert_eq!(batch.stop_reason, None);
+    }
+
+    #[tokio::test]
+    async fn test_batch_error_after_a_claim_returns_the_partial_batch() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1, 2, 3]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        store.fail_candidate_lookups_after(1);
+
+        let batch = dispatcher.assign_processes(Uuid::new_v4(), 3).await.unwrap();
+
+        assert_eq!(batch.processes.len(), 1);
+        assert_eq!(batch.stop_reason, Some(BatchStopReason::Error));
+        let error = dispatcher.assign_processes(Uuid::new_v4(), 3).await;
+        assert!(error.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_process_state_finishes_assigned_process() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        let process_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+        dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+
+        dispatcher
+            .update_process_state(process_id, "completed")
+            .await
+            .unwrap();
+
+        let process = store.get_process(process_id).await.unwrap().unwrap();
+        assert_eq!(process.dispatch_state().unwrap(), DispatchState::Completed);
+        assert_eq!(dispatcher.busiest_source_latencies(1)[0].finished, 1);
+        assert_eq!(dispatcher.latency_histograms()[&1].count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_process_state_of_unknown_or_unassigned_process() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        let unassigned_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+
+        for process_id in [Uuid::new_v4(), unassigned_id] {
+            let res = dispatcher.update_process_state(process_id, "failed").await;
+            assert!(matches!(res, Err(ReportFinishError::NotFound(id)) if id == process_id));
+        }
+    }
+
+    struct FinishCounter(AtomicUsize);
+
+    impl PostCommitHook for FinishCounter {
+        fn name(&self) -> &str {
+            "finish_counter"
+        }
+
+        fn on_commit(&self, event: &ProcessEvent) -> Result<(), HookError> {
+            if matches!(event, ProcessEvent::Finished { .. }) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_finish_report_has_no_side_effects() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        let finish_counter = Arc::new(FinishCounter(AtomicUsize::new(0)));
+        dispatcher.post_commit_hooks().register(finish_counter.clone());
+        dispatcher.run_one_cycle().await.unwrap();
+        let process_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+        dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+
+        let first = dispatcher.report_process_finish(process_id, REPORT_STATUS_SUCCESS).await;
+        let second = dispatcher.update_process_state(process_id, "failed").await;
+
+        assert!(first.is_ok());
+        assert!(matches!(
+            second,
+            Err(ReportFinishError::AlreadyFinished(_, DispatchState::Completed))
+        ));
+        let process = store.get_process(process_id).await.unwrap().unwrap();
+        assert_eq!(process.dispatch_state().unwrap(), DispatchState::Completed);
+        assert_eq!(dispatcher.latency_histograms()[&1].count, 1);
+        assert_eq!(finish_counter.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_process_state_rejects_unfinished_states() {
+        let dispatcher = in_memory_dispatcher(vec![]);
+
+        for state in ["processing", "pending", "done"] {
+            let res = dispatcher.update_process_state(Uuid::new_v4(), state).await;
+            assert!(matches!(res, Err(ReportFinishError::NotFinishedState(v)) if v == state));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_process_source_inserts_once() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Arc::new(Dispatcher::new(&EnvParams::for_tests(), store.clone()));
+        let start = Arc::new(tokio::sync::Barrier::new(16));
+
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let dispatcher = dispatcher.clone();
+                let start = start.clone();
+                tokio::spawn(async move {
+                    start.wait().await;
+                    dispatcher
+                        .process_source(1, &CancellationToken::new())
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+        let mut created = 0;
+        for task in tasks {
+            created += task.await.unwrap();
+        }
+
+        assert_eq!(created, 1);
+        let filter = ProcessFilter {
+            source_id: Some(1),
+            limit: 50,
+            ..ProcessFilter::default()
+        };
+        assert_eq!(store.list_processes(&filter).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reset_supervisor_requeues_only_its_processing_processes() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1, 2, 3]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        let (restarted, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let assigned_id = |outcome| match outcome {
+            AssignOutcome::Assigned(process) => Uuid::parse_str(&process.id).unwrap(),
+            AssignOutcome::NoWork(_) => panic!("expected an assigned process"),
+        };
+        let running = assigned_id(dispatcher.assign_process(restarted).await.unwrap());
+        let finished = assigned_id(dispatcher.assign_process(restarted).await.unwrap());
+        let foreign = assigned_id(dispatcher.assign_process(other).await.unwrap());
+        dispatcher
+            .report_process_finish(finished, REPORT_STATUS_SUCCESS)
+            .await
+            .unwrap();
+
+        assert_eq!(dispatcher.reset_supervisor(restarted).await.unwrap(), 1);
+
+        let process = store.get_process(running).await.unwrap().unwrap();
+        assert_eq!(process.dispatch_state().unwrap(), DispatchState::Created);
+        assert_eq!(process.supervisor_id, None);
+        let process = store.get_process(finished).await.unwrap().unwrap();
+        assert_eq!(process.dispatch_state().unwrap(), DispatchState::Completed);
+        let process = store.get_process(foreign).await.unwrap().unwrap();
+        assert_eq!(process.supervisor_id, Some(other));
+        assert_eq!(dispatcher.reset_supervisor(restarted).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_released_process_is_claimable_again() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        let process_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+        let (crashed, other) = (Uuid::new_v4(), Uuid::new_v4());
+        dispatcher.assign_process(crashed).await.unwrap();
+        assert!(matches!(
+            dispatcher.assign_process(other).await.unwrap(),
+            AssignOutcome::NoWork(_)
+        ));
+
+        assert!(dispatcher.release_process(process_id).await.unwrap());
+
+        let process = store.get_process(process_id).await.unwrap().unwrap();
+        assert_eq!(process.dispatch_state().unwrap(), DispatchState::Created);
+        assert_eq!(process.supervisor_id, None);
+        let assigned = dispatcher.assign_process(other).await.unwrap();
+        assert!(
+            matches!(assigned, AssignOutcome::Assigned(process) if process.id == process_id.to_string())
+        );
+        assert!(!dispatcher.release_process(Uuid::new_v4()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_release_leaves_queued_processes_untouched() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        let pending_id = store
+            .insert_new_process(1, DispatchState::Pending, ProcessingMode::Regular)
+            .await
+            .unwrap();
+
+        assert!(!dispatcher.release_process(pending_id).await.unwrap());
+
+        let process = store.get_process(pending_id).await.unwrap().unwrap();
+        assert_eq!(process.dispatch_state().unwrap(), DispatchState::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_release_of_an_error_process_depends_on_error_is_terminal() {
+        for (store, released) in [
+            (InMemoryProcessStore::new(vec![1]), true),
+            (InMemoryProcessStore::new(vec![1]).with_terminal_error(), false),
+        ] {
+            let store = Arc::new(store);
+            let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+            dispatcher.run_one_cycle().await.unwrap();
+            let process_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+            dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+            store.set_raw_state(process_id, &DispatchState::Error.to_string());
+
+            assert_eq!(dispatcher.release_process(process_id).await.unwrap(), released);
+
+            let process = store.get_process(process_id).await.unwrap().unwrap();
+            assert_eq!(process.supervisor_id.is_none(), released);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_processes_filters_and_pages() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1, 2, 3]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        let page = |source_id, state, limit, offset| ProcessFilter {
+            source_id,
+            state,
+            limit,
+            offset,
+        };
+
+        let all = dispatcher.list_processes(&page(None, None, 50, 0)).await.unwrap();
+        let mut source_ids: Vec<u32> = all.iter().map(|process| process.source_id).collect();
+        source_ids.sort();
+        assert_eq!(source_ids, vec![1, 2, 3]);
+
+        let of_source = dispatcher.list_processes(&page(Some(2), None, 50, 0)).await.unwrap();
+        assert_eq!(of_source.len(), 1);
+        assert_eq!(of_source[0].source_id, 2);
+
+        let first = dispatcher.list_processes(&page(None, None, 2, 0)).await.unwrap();
+        let rest = dispatcher.list_processes(&page(None, None, 2, 2)).await.unwrap();
+        assert_eq!((first.len(), rest.len()), (2, 1));
+
+        let finished = Some(DispatchState::Completed);
+        assert!(dispatcher.list_processes(&page(None, finished, 50, 0)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_processes_is_newest_first_and_skips_unknown_states() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![]));
+        let mut process_ids = Vec::new();
+        for source_id in [1, 2, 3, 4] {
+            process_ids.push(
+                store
+                    .insert_new_process(source_id, DispatchState::Created, ProcessingMode::Regular)
+                    .await
+                    .unwrap(),
+            );
+            //distinct created_at millis
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        store.set_raw_state(process_ids[1], "paused");
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store);
+
+        let processes = dispatcher
+            .list_processes(&ProcessFilter {
+                source_id: None,
+                state: None,
+                limit: 50,
+                offset: 0,
+            })
+            .await
+            .unwrap();
+
+        let source_ids: Vec<u32> = processes.iter().map(|process| process.source_id).collect();
+        assert_eq!(source_ids, vec![4, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_reaper_requeues_only_stale_processing_processes() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1, 2]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        let stale_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+        let fresh_id = store.get_latest_process_for(2).await.unwrap().unwrap().uuid;
+        let supervisor_id = Uuid::new_v4();
+        dispatcher.assign_processes(supervisor_id, 2).await.unwrap();
+        store.set_updated_at(stale_id, "2000-01-01 00:00:00.000");
+
+        let reset_cnt = dispatcher
+            .reap_stale_processes(Duration::from_secs(600))
+            .await
+            .unwrap();
+
+        assert_eq!(reset_cnt, 1);
+        let stale = store.get_process(stale_id).await.unwrap().unwrap();
+        assert_eq!(stale.dispatch_state().unwrap(), DispatchState::Created);
+        assert_eq!(stale.supervisor_id, None);
+        let fresh = store.get_process(fresh_id).await.unwrap().unwrap();
+        assert_eq!(fresh.dispatch_state().unwrap(), DispatchState::Processing);
+        assert_eq!(fresh.supervisor_id, Some(supervisor_id));
+    }
+
+    #[tokio::test]
+    async fn test_create_sandbox_processes_without_same_day_guard() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+
+        let first_id = dispatcher
+            .create_process(1, ProcessingMode::Sandbox)
+            .await
+            .unwrap();
+        let second_id = dispatcher
+            .create_process(1, ProcessingMode::Sandbox)
+            .await
+            .unwrap();
+
+        assert_ne!(first_id, second_id);
+        for process_id in [first_id, second_id] {
+            let process = store.get_process(process_id).await.unwrap().unwrap();
+            assert_eq!(process.mode, 2);
+            assert_eq!(process.source_id, 1);
+            assert_eq!(process.dispatch_state().unwrap(), DispatchState::Created);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finished_sandbox_process_does_not_block_regular_scheduling() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        let sandbox_id = dispatcher
+            .create_process(1, ProcessingMode::Sandbox)
+            .await
+            .unwrap();
+
+        let while_running = dispatcher.run_one_cycle().await.unwrap();
+        store.set_raw_state(sandbox_id, &DispatchState::Completed.to_string());
+        let after_finish = dispatcher.run_one_cycle().await.unwrap();
+
+        assert_eq!(while_running.processes_created, 0);
+        assert_eq!(after_finish.processes_created, 1);
+        let latest = store.get_latest_process_for(1).await.unwrap().unwrap();
+        assert_ne!(latest.uuid, sandbox_id);
+        assert_eq!(latest.mode, u8::from(ProcessingMode::Regular));
+    }
+
+    #[tokio::test]
+    async fn test_create_process_refuses_the_other_unfinished_mode() {
+        let dispatcher = in_memory_dispatcher(vec![1]);
+        dispatcher.run_one_cycle().await.unwrap();
+
+        let sandbox = dispatcher.create_process(1, ProcessingMode::Sandbox).await;
+        let regular = dispatcher.create_process(1, ProcessingMode::Regular).await;
+
+        assert!(matches!(
+            sandbox,
+            Err(CreateProcessError::ModeConflict(1, ProcessingMode::Regular))
+        ));
+        assert!(regular.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_claims_have_one_winner() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        let process_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let (first_rows, second_rows) = tokio::join!(
+            store.try_claim_process(process_id, first, DispatchState::Processing),
+            store.try_claim_process(process_id, second, DispatchState::Processing),
+        );
+
+        let (first_rows, second_rows) = (first_rows.unwrap(), second_rows.unwrap());
+        assert_eq!(first_rows + second_rows, 1);
+        let winner = if first_rows == 1 { first } else { second };
+        let process = store.get_process(process_id).await.unwrap().unwrap();
+        assert_eq!(process.supervisor_id, Some(winner));
+    }
+
+    #[tokio::test]
+    async fn test_expired_process_is_not_claimed() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let process_id = store
+            .insert_new_process(1, DispatchState::Created, ProcessingMode::Regular)
+            .await
+            .unwrap();
+        //expired by the max-age sweep after the candidate was read
+        store.set_raw_state(process_id, &DispatchState::Failed.to_string());
+
+        let rows = store
+            .try_claim_process(process_id, Uuid::new_v4(), DispatchState::Processing)
+            .await
+            .unwrap();
+
+        assert_eq!(rows, 0);
+        let process = store.get_process(process_id).await.unwrap().unwrap();
+        assert_eq!(process.dispatch_state().unwrap(), DispatchState::Failed);
+        assert_eq!(process.supervisor_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_higher_class_is_assigned_first() {
+        let process_classes: ProcessClasses =
+            "interactive:sandbox,batch:regular".parse().unwrap();
+        let store = Arc::new(
+            InMemoryProcessStore::new(vec![1]).with_process_classes(process_classes.clone()),
+        );
+        let dispatcher = Dispatcher::new(
+            &EnvParams::for_tests().with_process_classes(process_classes),
+            store.clone(),
+        );
+        let batch_id = store
+            .insert_new_process(1, DispatchState::Pending, ProcessingMode::Regular)
+            .await
+            .unwrap();
+        let interactive_id = store
+            .insert_new_process(2, DispatchState::Pending, ProcessingMode::Sandbox)
+            .await
+            .unwrap();
+
+        let first = dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+        let second = dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+
+        assert!(
+            matches!(first, AssignOutcome::Assigned(process) if process.id == interactive_id.to_string())
+        );
+        assert!(
+            matches!(second, AssignOutcome::Assigned(process) if process.id == batch_id.to_string())
+        );
+        let status = dispatcher.process_status(batch_id).await.unwrap().unwrap();
+        assert_eq!(status.class, "batch");
+    }
+
+    #[tokio::test]
+    async fn test_pool_health_reports_each_pool() {
+        let dispatcher = in_memory_dispatcher(vec![]);
+
+        let health = dispatcher.pool_health(&CancellationToken::new()).await.unwrap();
+
+        assert_eq!(health, BTreeMap::from([("pd", true)]));
+    }
+
+    #[tokio::test]
+    async fn test_info_reports_build_and_db_versions() {
+        let info = in_memory_dispatcher(vec![]).info().await.unwrap();
+
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.db_version, "in-memory");
+        assert_eq!(info.migration_version, None);
+    }
+
+    async fn finished_process_without_created_at(
+        env_params: EnvParams,
+    ) -> (Dispatcher, Arc<InMemoryProcessStore>) {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&env_params, store.clone());
+        let process_id = store
+            .insert_new_process(1, DispatchState::Completed, ProcessingMode::Regular)
+            .await
+            .unwrap();
+        store.clear_created_at(process_id);
+        (dispatcher, store)
+    }
+
+    #[tokio::test]
+    async fn test_null_created_at_creates_a_new_process_by_default() {
+        let (dispatcher, _) = finished_process_without_created_at(EnvParams::for_tests()).await;
+
+        let report = dispatcher.run_one_cycle().await.unwrap();
+
+        assert_eq!(report.processes_created, 1);
+    }
+
+    #[tokio::test]
+    async fn test_null_created_at_skips_the_source_if_configured() {
+        let (dispatcher, _) = finished_process_without_created_at(
+            EnvParams::for_tests().with_null_created_at(NullCreatedAt::Skip),
+        )
+        .await;
+
+        let report = dispatcher.run_one_cycle().await.unwrap();
+
+        assert_eq!(report.processes_created, 0);
+        assert_eq!(report.sources_skipped, 1);
+    }
+
+    #[tokio::test]
+    async fn test_null_created_at_is_assigned_as_oldest() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1, 2]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        store
+            .insert_new_process(1, DispatchState::Pending, ProcessingMode::Regular)
+            .await
+            .unwrap();
+        let unknown_age_id = store
+            .insert_new_process(2, DispatchState::Pending, ProcessingMode::Regular)
+            .await
+            .unwrap();
+        store.clear_created_at(unknown_age_id);
+
+        let assigned = dispatcher.assign_process(Uuid::new_v4()).await.unwrap();
+
+        let AssignOutcome::Assigned(process) = assigned else {
+            panic!("expected an assignment, got {:?}", assigned);
+        };
+        assert_eq!(process.id, unknown_age_id.to_string());
+        assert_eq!(process.created_at, DateTime::<Utc>::UNIX_EPOCH);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_reruns_queue_one_process() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        dispatcher.run_one_cycle().await.unwrap();
+        let process_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+        store.set_raw_state(process_id, &DispatchState::Completed.to_string());
+
+        let (first, second) = tokio::join!(
+            dispatcher.rerun_process(process_id),
+            dispatcher.rerun_process(process_id)
+        );
+
+        let reruns = [first, second];
+        assert_eq!(reruns.iter().filter(|rerun| rerun.is_ok()).count(), 1);
+        assert!(reruns.iter().any(|rerun| matches!(
+            rerun,
+            Err(RerunError::SourceBusy(1, ProcessingMode::Regular))
+        )));
+        let waiting = store
+            .list_processes(&ProcessFilter {
+                source_id: Some(1),
+                state: Some(DispatchState::Created),
+                limit: 50,
+                offset: 0,
+            })
+            .await
+            .unwrap();
+        assert_eq!(waiting.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rerun_is_refused_while_the_source_is_queued() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        let finished_id = store
+            .insert_new_process(1, DispatchState::Completed, ProcessingMode::Regular)
+            .await
+            .unwrap();
+        //the scheduler's process of the day, not assigned yet
+        store
+            .insert_new_process(1, DispatchState::Created, ProcessingMode::Regular)
+            .await
+            .unwrap();
+
+        let rerun = dispatcher.rerun_process(finished_id).await;
+
+        assert!(matches!(rerun, Err(RerunError::SourceBusy(1, _))));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_state_is_an_error_not_a_panic() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store.clone());
+        let cancellation_token = CancellationToken::new();
+        dispatcher.prepare_schedule(&cancellation_token).await.unwrap();
+        let process_id = store.get_latest_process_for(1).await.unwrap().unwrap().uuid;
+        store.set_raw_state(process_id, "paused");
+
+        let status = dispatcher.process_status(process_id).await;
+        let rerun = dispatcher.rerun_process(process_id).await;
+
+        assert!(matches!(
+            status,
+            Err(DispatcherError::InvalidState(e)) if e.contains("'paused'")
+        ));
+        assert!(matches!(rerun, Err(RerunError::InvalidState(_))));
+    }
+
+    #[tokio::test]
+    async fn test_queue_position_counts_older_waiting_processes() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![]));
+        let mut process_ids = Vec::new();
+        for source_id in [1, 2, 1] {
+            process_ids.push(
+                store
+                    .insert_new_process(source_id, DispatchState::Created, ProcessingMode::Regular)
+                    .await
+                    .unwrap(),
+            );
+            //distinct created_at millis
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store);
+
+        let last = dispatcher.queue_position(process_ids[2]).await.unwrap().unwrap();
+        assert_eq!(dispatcher.queue_depth().await.unwrap(), 3);
+        dispatcher
+            .assign_process_by_id(Uuid::new_v4(), process_ids[0])
+            .await
+            .unwrap();
+        assert_eq!(dispatcher.queue_depth().await.unwrap(), 2);
+        assert_eq!(dispatcher.in_flight_count().await.unwrap(), 1);
+        let assigned = dispatcher.queue_position(process_ids[0]).await.unwrap().unwrap();
+        let last_after_assign = dispatcher.queue_position(process_ids[2]).await.unwrap().unwrap();
+
+        assert_eq!((last.source_position, last.global_position), (Some(1), Some(2)));
+        assert_eq!((assigned.source_position, assigned.global_position), (None, None));
+        assert_eq!(
+            (last_after_assign.source_position, last_after_assign.global_position),
+            (Some(0), Some(1))
+        );
+        assert!(dispatcher.queue_position(Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resume_order_starts_after_cursor() {
+        assert_eq!(resume_order(vec![5, 1, 9, 3], 0), vec![1, 3, 5, 9]);
+        assert_eq!(resume_order(vec![5, 1, 9, 3], 3), vec![5, 9, 1, 3]);
+        assert_eq!(resume_order(vec![5, 1, 9, 3], 9), vec![1, 3, 5, 9]);
+    }
+
+    #[test]
+    fn test_day_start_is_local_midnight_in_utc() {
+        let berlin = DispatchTimeFormatter::new(chrono_tz::Europe::Berlin);
+        let utc = DispatchTimeFormatter::new(UTC);
+
+        let berlin_day_start =
+            DispatchTimeFormatter::day_start_dt(berlin.db_to_dt("2024-10-13 13:13:23.000", None));
+        let utc_day_start =
+            DispatchTimeFormatter::day_start_dt(utc.db_to_dt("2024-10-13 13:13:23.000", None));
+
+        //Europe/Berlin is UTC+2 in October
+        assert_eq!(
+            DispatchTimeFormatter::dt_to_db(berlin_day_start),
+            "2024-10-12 22:00:00.000"
+        );
+        assert_eq!(
+            DispatchTimeFormatter::dt_to_db(utc_day_start),
+            "2024-10-13 00:00:00.000"
+        );
     }
 }
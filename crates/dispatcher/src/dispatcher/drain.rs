@@ -0,0 +1,105 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Progress of `POST /drain_and_exit`, reported by `GET /drain_and_exit/status`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DrainStatus {
+    Idle,
+    /// New work is refused while `in_flight` assigned processes are still running.
+    Draining { in_flight: u64 },
+    Drained,
+    TimedOut { in_flight: u64 },
+}
+
+impl DrainStatus {
+    /// Status after a poll of the in-flight count, given the time spent draining.
+    pub(super) fn after_poll(in_flight: u64, elapsed: Duration, timeout: Duration) -> DrainStatus {
+        if in_flight == 0 {
+            DrainStatus::Drained
+        } else if elapsed >= timeout {
+            DrainStatus::TimedOut { in_flight }
+        } else {
+            DrainStatus::Draining { in_flight }
+        }
+    }
+
+    pub fn in_flight(&self) -> u64 {
+        match self {
+            DrainStatus::Draining { in_flight } | DrainStatus::TimedOut { in_flight } => *in_flight,
+            DrainStatus::Idle | DrainStatus::Drained => 0,
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, DrainStatus::Drained | DrainStatus::TimedOut { .. })
+    }
+}
+
+pub(super) struct DrainState {
+    status: Mutex<DrainStatus>,
+}
+
+impl Default for DrainState {
+    fn default() -> Self {
+        DrainState {
+            status: Mutex::new(DrainStatus::Idle),
+        }
+    }
+}
+
+impl DrainState {
+    pub fn status(&self) -> DrainStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.status.lock().unwrap() != DrainStatus::Idle
+    }
+
+    /// Switches to draining once; `false` if a drain has already been started.
+    pub fn start(&self) -> bool {
+        let mut status = self.status.lock().unwrap();
+        if *status != DrainStatus::Idle {
+            return false;
+        }
+        *status = DrainStatus::Draining { in_flight: 0 };
+        true
+    }
+
+    pub fn set(&self, status: DrainStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_after_poll() {
+        let timeout = Duration::from_secs(10);
+        assert_eq!(
+            DrainStatus::after_poll(0, Duration::from_secs(30), timeout),
+            DrainStatus::Drained
+        );
+        assert_eq!(
+            DrainStatus::after_poll(2, Duration::from_secs(3), timeout),
+            DrainStatus::Draining { in_flight: 2 }
+        );
+        assert_eq!(
+            DrainStatus::after_poll(2, timeout, timeout),
+            DrainStatus::TimedOut { in_flight: 2 }
+        );
+    }
+
+    #[test]
+    fn test_drain_starts_only_once() {
+        let drain = DrainState::default();
+        assert!(!drain.is_draining());
+        assert!(drain.start());
+        assert!(drain.is_draining());
+        assert!(!drain.start());
+    }
+}
@@ -4,6 +4,8 @@ use std::fmt::Display;
 pub enum DispatcherError {
     DbError(sqlx::Error),
     TerminatingSignalReceived,
+    /// A row with a `state` / `mode` value this build does not know.
+    InvalidState(String),
 }
 
 impl Display for DispatcherError {
@@ -11,6 +13,7 @@ impl Display for DispatcherError {
         match self {
             DispatcherError::DbError(e) => write!(f, "DbError: {}", e),
             DispatcherError::TerminatingSignalReceived => write!(f, "TerminatingSignalReceived"),
+            DispatcherError::InvalidState(e) => write!(f, "InvalidState: {}", e),
         }
     }
 }
@@ -19,7 +22,7 @@ impl std::error::Error for DispatcherError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             DispatcherError::DbError(e) => Some(e),
-            DispatcherError::TerminatingSignalReceived => None,
+            DispatcherError::TerminatingSignalReceived | DispatcherError::InvalidState(_) => None,
         }
     }
 }
@@ -35,3 +38,9 @@ impl From<crate::cancellation_ext::CancellationError> for DispatcherError {
         DispatcherError::TerminatingSignalReceived
     }
 }
+
+impl From<shared::ParseError> for DispatcherError {
+    fn from(e: shared::ParseError) -> Self {
+        DispatcherError::InvalidState(e.to_string())
+    }
+}
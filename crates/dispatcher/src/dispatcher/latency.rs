@@ -0,0 +1,184 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Only the most recent samples per source are kept for percentiles.
+const MAX_SAMPLES_PER_SOURCE: usize = 256;
+/// Upper bound of the map size; samples of sources beyond it are dropped.
+const MAX_TRACKED_SOURCES: usize = 10_000;
+/// Histogram label values are `source_id % LATENCY_SOURCE_BUCKETS`, which keeps
+/// the series count bounded however many sources exist.
+const LATENCY_SOURCE_BUCKETS: u32 = 16;
+/// Upper bounds, in seconds, of the latency histogram buckets (`+Inf` implied).
+pub const LATENCY_BUCKET_BOUNDS_SECS: [f64; 9] =
+    [1.0, 10.0, 60.0, 300.0, 900.0, 3600.0, 4.0 * 3600.0, 12.0 * 3600.0, 86400.0];
+
+/// Created → finished latency per source, kept in memory since start.
+#[derive(Default)]
+pub(super) struct SourceLatencies {
+    sources: Mutex<HashMap<u32, SourceSamples>>,
+    histograms: Mutex<BTreeMap<u32, LatencyHistogram>>,
+}
+
+/// Latency histogram of one source bucket, in the shape of a Prometheus histogram.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    /// Count per bucket of `LATENCY_BUCKET_BOUNDS_SECS`, not cumulative; the
+    /// last entry counts the samples above every bound.
+    pub bucket_counts: [u64; LATENCY_BUCKET_BOUNDS_SECS.len() + 1],
+    pub sum_secs: f64,
+    pub count: u64,
+}
+
+impl LatencyHistogram {
+    fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKET_BOUNDS_SECS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(LATENCY_BUCKET_BOUNDS_SECS.len());
+        self.bucket_counts[bucket] += 1;
+        self.sum_secs += secs;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct SourceSamples {
+    finished: u64,
+    samples_ms: VecDeque<u64>,
+}
+
+/// Entry of `GET /stats/latency`.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SourceLatency {
+    pub source_id: u32,
+    pub finished: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+}
+
+impl SourceLatencies {
+    pub fn record(&self, source_id: u32, latency: Duration) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry(source_id % LATENCY_SOURCE_BUCKETS)
+            .or_default()
+            .observe(latency);
+
+        let mut sources = self.sources.lock().unwrap();
+        if sources.len() >= MAX_TRACKED_SOURCES && !sources.contains_key(&source_id) {
+            return;
+        }
+        let source = sources.entry(source_id).or_default();
+        source.finished += 1;
+        if source.samples_ms.len() == MAX_SAMPLES_PER_SOURCE {
+            source.samples_ms.pop_front();
+        }
+        source
+            .samples_ms
+            .push_back(latency.as_millis().try_into().unwrap_or(u64::MAX));
+    }
+
+    /// Latency summary of the `limit` sources with the most finished processes.
+    pub fn busiest(&self, limit: usize) -> Vec<SourceLatency> {
+        let sources = self.sources.lock().unwrap();
+        let mut latencies: Vec<SourceLatency> = sources
+            .iter()
+            .map(|(&source_id, source)| {
+                let mut samples: Vec<u64> = source.samples_ms.iter().copied().collect();
+                samples.sort_unstable();
+                SourceLatency {
+                    source_id,
+                    finished: source.finished,
+                    p50_ms: percentile(&samples, 50),
+                    p95_ms: percentile(&samples, 95),
+                }
+            })
+            .collect();
+        latencies.sort_by(|a, b| {
+            b.finished
+                .cmp(&a.finished)
+                .then(a.source_id.cmp(&b.source_id))
+        });
+        latencies.truncate(limit);
+        latencies
+    }
+
+    /// Latency histograms by source bucket (`source_id % 16`).
+    pub fn histograms(&self) -> BTreeMap<u32, LatencyHistogram> {
+        self.histograms.lock().unwrap().clone()
+    }
+}
+
+/// Nearest-rank percentile of sorted samples.
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let samples: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&samples, 50), 50);
+        assert_eq!(percentile(&samples, 95), 95);
+        assert_eq!(percentile(&[7], 95), 7);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+
+    #[test]
+    fn test_histogram_buckets_by_source_bucket() {
+        let latencies = SourceLatencies::default();
+        latencies.record(1, Duration::from_millis(500));
+        latencies.record(17, Duration::from_secs(30));
+        latencies.record(2, Duration::from_secs(2 * 86400));
+
+        let histograms = latencies.histograms();
+
+        assert_eq!(histograms.len(), 2);
+        let first = &histograms[&1];
+        assert_eq!(first.count, 2);
+        assert_eq!(first.bucket_counts[0], 1);
+        assert_eq!(first.bucket_counts[2], 1);
+        assert_eq!(first.sum_secs, 30.5);
+        assert_eq!(histograms[&2].bucket_counts[LATENCY_BUCKET_BOUNDS_SECS.len()], 1);
+    }
+
+    #[test]
+    fn test_busiest_sources_first() {
+        let latencies = SourceLatencies::default();
+        latencies.record(1, Duration::from_millis(100));
+        latencies.record(2, Duration::from_millis(10));
+        latencies.record(2, Duration::from_millis(30));
+        latencies.record(3, Duration::from_millis(5));
+
+        let busiest = latencies.busiest(2);
+
+        assert_eq!(
+            busiest,
+            vec![
+                SourceLatency {
+                    source_id: 2,
+                    finished: 2,
+                    p50_ms: 10,
+                    p95_ms: 30,
+                },
+                SourceLatency {
+                    source_id: 1,
+                    finished: 1,
+                    p50_ms: 100,
+                    p95_ms: 100,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use super::{HookError, PostCommitHook, ProcessEvent};
+use chrono::{SecondsFormat, Utc};
+use shared::DispatchState;
+use tracing::{info, Subscriber};
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// `tracing` target of lifecycle events, for routing them apart from free text.
+pub const LIFECYCLE_TARGET: &str = "process_lifecycle";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LifecycleEventKind {
+    Created,
+    Assigned,
+    Completed,
+    Failed,
+}
+
+impl LifecycleEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LifecycleEventKind::Created => "created",
+            LifecycleEventKind::Assigned => "assigned",
+            LifecycleEventKind::Completed => "completed",
+            LifecycleEventKind::Failed => "failed",
+        }
+    }
+}
+
+/// Post-commit hook logging every process transition on `LIFECYCLE_TARGET` as
+/// structured fields; registered by `Dispatcher::new` with `LIFECYCLE_EVENTS=true`.
+/// Field names are a stable schema consumed by log pipelines.
+pub struct LifecycleEvents;
+
+impl PostCommitHook for LifecycleEvents {
+    fn name(&self) -> &str {
+        "lifecycle_events"
+    }
+
+    fn on_commit(&self, event: &ProcessEvent) -> Result<(), HookError> {
+        let (kind, process_id, source_id, supervisor_id, state) = match event {
+            ProcessEvent::Created {
+                process_id,
+                source_id,
+            } => (
+                LifecycleEventKind::Created,
+                process_id,
+                source_id,
+                None,
+                &DispatchState::Created,
+            ),
+            ProcessEvent::Assigned {
+                process_id,
+                source_id,
+                supervisor_id,
+            } => (
+                LifecycleEventKind::Assigned,
+                process_id,
+                source_id,
+                Some(supervisor_id),
+                &DispatchState::Processing,
+            ),
+            ProcessEvent::Finished {
+                process_id,
+                source_id,
+                supervisor_id,
+                state,
+            } => {
+                let kind = if *state == DispatchState::Completed {
+                    LifecycleEventKind::Completed
+                } else {
+                    LifecycleEventKind::Failed
+                };
+                (kind, process_id, source_id, supervisor_id.as_ref(), state)
+            }
+        };
+        info!(
+            target: LIFECYCLE_TARGET,
+            event = kind.as_str(),
+            process_id = %process_id,
+            source_id = *source_id,
+            supervisor_id = supervisor_id.map(display),
+            state = %state,
+            ts = %Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        );
+        Ok(())
+    }
+}
+
+/// Layer writing only `LIFECYCLE_TARGET` events, each as one flat JSON object per
+/// line holding just the event fields (no timestamp, level or span prefix).
+pub fn json_layer<S, W>(make_writer: W) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .json()
+        .flatten_event(true)
+        .without_time()
+        .with_level(false)
+        .with_target(false)
+        .with_current_span(false)
+        .with_span_list(false)
+        .with_writer(make_writer)
+        .with_filter(filter_fn(|metadata| metadata.target() == LIFECYCLE_TARGET))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+    use uuid::Uuid;
+
+    #[derive(Clone, Default)]
+    struct CapturedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for CapturedOutput {
+        type Writer = CapturedOutput;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn logged_lines(events: &[ProcessEvent]) -> Vec<serde_json::Value> {
+        let output = CapturedOutput::default();
+        let subscriber = tracing_subscriber::registry().with(json_layer(output.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            for event in events {
+                LifecycleEvents.on_commit(event).unwrap();
+            }
+            info!("free text stays out of the lifecycle stream");
+        });
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_events_are_flat_json_lines() {
+        let lines = logged_lines(&[
+            ProcessEvent::Assigned {
+                process_id: Uuid::nil(),
+                source_id: 42,
+                supervisor_id: Uuid::nil(),
+            },
+            ProcessEvent::Created {
+                process_id: Uuid::nil(),
+                source_id: 42,
+            },
+        ]);
+
+        assert_eq!(lines.len(), 2);
+        let assigned = &lines[0];
+        assert_eq!(assigned["event"], "assigned");
+        assert_eq!(assigned["process_id"], "00000000-0000-0000-0000-000000000000");
+        assert_eq!(assigned["source_id"], 42);
+        assert_eq!(assigned["supervisor_id"], "00000000-0000-0000-0000-000000000000");
+        assert_eq!(assigned["state"], "processing");
+        assert!(assigned["ts"].as_str().unwrap().ends_with('Z'));
+        assert_eq!(assigned.as_object().unwrap().len(), 6);
+        let created = &lines[1];
+        assert_eq!(created["event"], "created");
+        assert!(created.get("supervisor_id").is_none());
+    }
+
+    #[test]
+    fn test_failed_and_error_finishes_are_failed_events() {
+        let finished = |state| ProcessEvent::Finished {
+            process_id: Uuid::nil(),
+            source_id: 1,
+            supervisor_id: Some(Uuid::nil()),
+            state,
+        };
+
+        let lines = logged_lines(&[
+            finished(DispatchState::Completed),
+            finished(DispatchState::Error),
+        ]);
+
+        assert_eq!(lines[0]["event"], "completed");
+        assert_eq!(lines[1]["event"], "failed");
+        assert_eq!(lines[1]["state"], "error");
+    }
+}
@@ -0,0 +1,148 @@
+use sqlx::mysql::MySqlDatabaseError;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+const MAX_LOCK_RETRIES: u32 = 3;
+const LOCK_RETRY_BASE_DELAY: Duration = Duration::from_millis(20);
+
+/// MySQL error numbers of a lost lock race. A deadlock (1213) rolls back the whole
+/// transaction, but a lock wait timeout (1205) with the default
+/// `innodb_rollback_on_timeout=OFF` rolls back only the failed statement. A retry
+/// is safe here only because every retried op is a single autocommit UPDATE;
+/// never pass a multi-statement transaction to `retry_on_lock_conflict`.
+const ER_LOCK_WAIT_TIMEOUT: u16 = 1205;
+const ER_LOCK_DEADLOCK: u16 = 1213;
+
+pub(super) fn is_lock_conflict(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .and_then(|db_error| db_error.try_downcast_ref::<MySqlDatabaseError>())
+        .is_some_and(|mysql_error| {
+            matches!(mysql_error.number(), ER_LOCK_DEADLOCK | ER_LOCK_WAIT_TIMEOUT)
+        })
+}
+
+/// Errors of the connection rather than of the statement: a dropped or refused
+/// connection, or no free connection in time. Retrying the whole operation is safe.
+pub(super) fn is_connection_error(e: &sqlx::Error) -> bool {
+    matches!(
+        e,
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolTimedOut
+    )
+}
+
+/// Re-runs `op` on deadlock / lock wait timeout errors, up to `MAX_LOCK_RETRIES`
+/// times with a linear backoff. Any other error is returned immediately. `op`
+/// must be a single autocommit statement, see `ER_LOCK_WAIT_TIMEOUT`.
+pub(super) async fn retry_on_lock_conflict<T, F, Fut>(
+    context: &str,
+    on_retry: impl Fn(),
+    op: F,
+) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    retry_when(is_lock_conflict, context, on_retry, op).await
+}
+
+async fn retry_when<T, F, Fut>(
+    is_retryable: impl Fn(&sqlx::Error) -> bool,
+    context: &str,
+    on_retry: impl Fn(),
+    mut op: F,
+) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if attempt < MAX_LOCK_RETRIES && is_retryable(&e) => {
+                attempt += 1;
+                warn!(
+                    "{}: lock conflict, retrying ({}/{}): {}",
+                    context, attempt, MAX_LOCK_RETRIES, e
+                );
+                on_retry();
+                tokio::time::sleep(LOCK_RETRY_BASE_DELAY * attempt).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let calls = AtomicU32::new(0);
+        let retries = AtomicU32::new(0);
+
+        let result = retry_when(
+            |_| true,
+            "test",
+            || {
+                retries.fetch_add(1, Ordering::SeqCst);
+            },
+            || async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(sqlx::Error::PoolTimedOut)
+                } else {
+                    Ok(1)
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(retries.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), _> = retry_when(
+            |_| true,
+            "test",
+            || {},
+            || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(sqlx::Error::PoolTimedOut)
+            },
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), MAX_LOCK_RETRIES + 1);
+    }
+
+    #[tokio::test]
+    async fn test_non_lock_errors_are_not_retried() {
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), _> = retry_on_lock_conflict("test", || {}, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_connection_errors() {
+        assert!(is_connection_error(&sqlx::Error::PoolTimedOut));
+        assert!(is_connection_error(&sqlx::Error::Io(std::io::Error::from(
+            std::io::ErrorKind::ConnectionReset
+        ))));
+        assert!(!is_connection_error(&sqlx::Error::PoolClosed));
+        assert!(!is_connection_error(&sqlx::Error::RowNotFound));
+    }
+}
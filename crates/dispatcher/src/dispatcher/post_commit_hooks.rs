@@ -0,0 +1,120 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+use tracing::{error, trace};
+use uuid::Uuid;
+
+/// A committed state change of a process row.
+#[derive(Debug, Clone)]
+pub enum ProcessEvent {
+    Created {
+        process_id: Uuid,
+        source_id: u32,
+    },
+    Assigned {
+        process_id: Uuid,
+        source_id: u32,
+        supervisor_id: Uuid,
+    },
+    Finished {
+        process_id: Uuid,
+        source_id: u32,
+        /// Owner at finish time.
+        supervisor_id: Option<Uuid>,
+        state: shared::DispatchState,
+    },
+}
+
+pub type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Best-effort side effect (metrics, webhook, broadcast, ...) executed after the
+/// DB change is committed. A hook must not block; long-running work should be
+/// spawned onto the runtime by the hook itself.
+pub trait PostCommitHook: Send + Sync {
+    fn name(&self) -> &str;
+    fn on_commit(&self, event: &ProcessEvent) -> Result<(), HookError>;
+}
+
+/// Registry of post-commit hooks owned by `Dispatcher`.
+///
+/// The DB state change is the unit of consistency: once it is committed, hook
+/// failures (errors or panics) are logged and never undo it or fail the request.
+#[derive(Default)]
+pub struct PostCommitHooks {
+    hooks: RwLock<Vec<Arc<dyn PostCommitHook>>>,
+}
+
+impl PostCommitHooks {
+    pub fn register(&self, hook: Arc<dyn PostCommitHook>) {
+        self.hooks.write().unwrap().push(hook);
+    }
+
+    pub(crate) fn run(&self, event: &ProcessEvent) {
+        let hooks = self.hooks.read().unwrap().clone();
+        for hook in hooks {
+            trace!("Running post-commit hook {} for {:?}", hook.name(), event);
+            match catch_unwind(AssertUnwindSafe(|| hook.on_commit(event))) {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => error!("Post-commit hook {} failed: {}", hook.name(), e),
+                Err(_) => error!("Post-commit hook {} panicked", hook.name()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingHook(AtomicUsize);
+
+    impl PostCommitHook for CountingHook {
+        fn name(&self) -> &str {
+            "counting"
+        }
+        fn on_commit(&self, _: &ProcessEvent) -> Result<(), HookError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct FailingHook;
+
+    impl PostCommitHook for FailingHook {
+        fn name(&self) -> &str {
+            "failing"
+        }
+        fn on_commit(&self, _: &ProcessEvent) -> Result<(), HookError> {
+            Err("webhook unreachable".into())
+        }
+    }
+
+    struct PanickingHook;
+
+    impl PostCommitHook for PanickingHook {
+        fn name(&self) -> &str {
+            "panicking"
+        }
+        fn on_commit(&self, _: &ProcessEvent) -> Result<(), HookError> {
+            panic!("hook bug")
+        }
+    }
+
+    #[test]
+    fn test_failing_hooks_do_not_affect_others() {
+        let hooks = PostCommitHooks::default();
+        let counting = Arc::new(CountingHook(AtomicUsize::new(0)));
+        hooks.register(Arc::new(FailingHook));
+        hooks.register(Arc::new(PanickingHook));
+        hooks.register(counting.clone());
+
+        hooks.run(&ProcessEvent::Finished {
+            process_id: Uuid::new_v4(),
+            source_id: 1,
+            supervisor_id: None,
+            state: shared::DispatchState::Completed,
+        });
+
+        assert_eq!(counting.0.load(Ordering::SeqCst), 1);
+    }
+}
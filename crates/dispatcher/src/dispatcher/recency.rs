@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A score halves after this long without new work from the source.
+const SCORE_HALF_LIFE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Decayed scores below this are dropped when the map is full.
+const MIN_SCORE: f64 = 0.01;
+/// Upper bound of the map size; bumps of sources beyond it are dropped.
+const MAX_TRACKED_SOURCES: usize = 10_000;
+
+/// Order in which `prepare_schedule` scans the sources (`SCAN_STRATEGY`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanStrategy {
+    /// Ascending source id, resuming after the last source of a capped cycle.
+    Fifo,
+    /// Sources that recently produced work first, then ascending source id. A
+    /// capped cycle is not resumed: the next one starts from the top again.
+    Recency,
+}
+
+impl FromStr for ScanStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fifo" => Ok(ScanStrategy::Fifo),
+            "recency" => Ok(ScanStrategy::Recency),
+            other => Err(format!("unknown scan strategy '{}'", other)),
+        }
+    }
+}
+
+/// Per-source score of created work, decaying exponentially over time.
+#[derive(Default)]
+pub(super) struct SourceRecency {
+    scores: Mutex<HashMap<u32, Score>>,
+}
+
+struct Score {
+    value: f64,
+    updated_at: Instant,
+}
+
+impl Score {
+    fn decayed(&self, now: Instant) -> f64 {
+        let half_lives = now.saturating_duration_since(self.updated_at).as_secs_f64()
+            / SCORE_HALF_LIFE.as_secs_f64();
+        self.value * 0.5_f64.powf(half_lives)
+    }
+}
+
+impl SourceRecency {
+    pub fn bump(&self, source_id: u32) {
+        self.bump_at(source_id, Instant::now());
+    }
+
+    /// Stable sort by descending score: sources without a score keep their
+    /// relative order behind the scored ones.
+    pub fn order(&self, source_ids: Vec<u32>) -> Vec<u32> {
+        self.order_at(source_ids, Instant::now())
+    }
+
+    fn bump_at(&self, source_id: u32, now: Instant) {
+        let mut scores = self.scores.lock().unwrap();
+        if scores.len() >= MAX_TRACKED_SOURCES && !scores.contains_key(&source_id) {
+            scores.retain(|_, score| score.decayed(now) >= MIN_SCORE);
+            if scores.len() >= MAX_TRACKED_SOURCES {
+                return;
+            }
+        }
+        let value = scores.get(&source_id).map_or(0.0, |score| score.decayed(now)) + 1.0;
+        scores.insert(
+            source_id,
+            Score {
+                value,
+                updated_at: now,
+            },
+        );
+    }
+
+    fn order_at(&self, mut source_ids: Vec<u32>, now: Instant) -> Vec<u32> {
+        let scores = self.scores.lock().unwrap();
+        let score_of =
+            |source_id: &u32| scores.get(source_id).map_or(0.0, |score| score.decayed(now));
+        source_ids.sort_by(|a, b| score_of(b).total_cmp(&score_of(a)));
+        source_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_sources_first_others_keep_order() {
+        let recency = SourceRecency::default();
+        let now = Instant::now();
+        recency.bump_at(7, now);
+        recency.bump_at(3, now);
+        recency.bump_at(3, now);
+
+        assert_eq!(recency.order_at(vec![1, 3, 5, 7, 9], now), vec![3, 7, 1, 5, 9]);
+    }
+
+    #[test]
+    fn test_scores_decay_over_time() {
+        let recency = SourceRecency::default();
+        let now = Instant::now();
+        recency.bump_at(1, now);
+        recency.bump_at(1, now);
+        recency.bump_at(2, now + 2 * SCORE_HALF_LIFE);
+
+        //source 1: 2 halved twice = 0.5 < source 2: 1
+        assert_eq!(recency.order_at(vec![1, 2], now + 2 * SCORE_HALF_LIFE), vec![2, 1]);
+        let score = Score {
+            value: 2.0,
+            updated_at: now,
+        };
+        assert_eq!(score.decayed(now + SCORE_HALF_LIFE), 1.0);
+    }
+
+    #[test]
+    fn test_scan_strategy_from_str() {
+        assert_eq!("FIFO".parse::<ScanStrategy>(), Ok(ScanStrategy::Fifo));
+        assert_eq!("recency".parse::<ScanStrategy>(), Ok(ScanStrategy::Recency));
+        assert!("random".parse::<ScanStrategy>().is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Processes written by this instance within the read-your-writes window
+/// (`READ_YOUR_WRITES_SECS`), whose status reads must go to the primary.
+pub(super) struct RecentWrites {
+    window: Duration,
+    written_at: Mutex<HashMap<Uuid, Instant>>,
+}
+
+impl RecentWrites {
+    pub fn new(window: Duration) -> Self {
+        RecentWrites {
+            window,
+            written_at: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record(&self, process_id: Uuid) {
+        if self.window.is_zero() {
+            return;
+        }
+        let mut written_at = self.written_at.lock().unwrap();
+        written_at.retain(|_, at| at.elapsed() < self.window);
+        written_at.insert(process_id, Instant::now());
+    }
+
+    pub fn contains(&self, process_id: Uuid) -> bool {
+        self.written_at
+            .lock()
+            .unwrap()
+            .get(&process_id)
+            .is_some_and(|at| at.elapsed() < self.window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_is_recent_within_window() {
+        let recent_writes = RecentWrites::new(Duration::from_millis(20));
+        let process_id = Uuid::new_v4();
+        recent_writes.record(process_id);
+        assert!(recent_writes.contains(process_id));
+        assert!(!recent_writes.contains(Uuid::new_v4()));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!recent_writes.contains(process_id));
+    }
+
+    #[test]
+    fn test_zero_window_tracks_nothing() {
+        let recent_writes = RecentWrites::new(Duration::ZERO);
+        let process_id = Uuid::new_v4();
+        recent_writes.record(process_id);
+        assert!(!recent_writes.contains(process_id));
+    }
+}
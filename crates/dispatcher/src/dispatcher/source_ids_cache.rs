@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// In-memory snapshot of active source ids (`SOURCE_CACHE_TTL_SECS`).
+/// A zero TTL disables the cache, so every schedule cycle re-queries `sources`.
+pub(super) struct SourceIdsCache {
+    ttl: Duration,
+    entry: Mutex<Option<(Instant, Arc<Vec<u32>>)>>,
+}
+
+impl SourceIdsCache {
+    pub fn new(ttl: Duration) -> Self {
+        SourceIdsCache {
+            ttl,
+            entry: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Returns the cached ids while they are younger than the TTL.
+    pub fn get(&self) -> Option<Arc<Vec<u32>>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut entry = self.entry.lock().unwrap();
+        match entry.as_ref() {
+            Some((stored_at, ids)) if stored_at.elapsed() < self.ttl => Some(ids.clone()),
+            Some(_) => {
+                *entry = None;
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn set(&self, ids: Arc<Vec<u32>>) {
+        if !self.is_enabled() {
+            return;
+        }
+        *self.entry.lock().unwrap() = Some((Instant::now(), ids));
+    }
+
+    pub fn invalidate(&self) {
+        *self.entry.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_cache_never_returns_ids() {
+        let cache = SourceIdsCache::new(Duration::ZERO);
+        cache.set(Arc::new(vec![1, 2]));
+        assert!(cache.get().is_none());
+    }
+
+    #[test]
+    fn test_cache_expires_after_ttl() {
+        let cache = SourceIdsCache::new(Duration::from_millis(20));
+        cache.set(Arc::new(vec![1, 2]));
+        assert_eq!(cache.get().as_deref(), Some(&vec![1, 2]));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(cache.get().is_none());
+    }
+
+    #[test]
+    fn test_invalidate_drops_cached_ids() {
+        let cache = SourceIdsCache::new(Duration::from_secs(60));
+        cache.set(Arc::new(vec![7]));
+        cache.invalidate();
+        assert!(cache.get().is_none());
+    }
+}
@@ -0,0 +1,104 @@
+use shared::ProcessingMode;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Counters accumulated since the dispatcher started.
+#[derive(Default)]
+pub(super) struct DispatcherStats {
+    aged_out_processes: AtomicU64,
+    lock_retries: AtomicU64,
+    assign_requests: AtomicU64,
+    assign_probes: AtomicU64,
+    assign_probe_cap_hits: AtomicU64,
+    promoted_processes: AtomicU64,
+    schedule_retries: AtomicU64,
+    processes_created: AtomicU64,
+    processes_assigned: Mutex<BTreeMap<String, u64>>,
+    assign_no_work: AtomicU64,
+}
+
+impl DispatcherStats {
+    pub fn add_aged_out_processes(&self, cnt: u64) {
+        self.aged_out_processes.fetch_add(cnt, Ordering::Relaxed);
+    }
+
+    pub fn add_lock_retry(&self) {
+        self.lock_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records one `assign_process` call that tried `probes` candidate sources.
+    pub fn add_assign_probes(&self, probes: u64) {
+        self.assign_requests.fetch_add(1, Ordering::Relaxed);
+        self.assign_probes.fetch_add(probes, Ordering::Relaxed);
+    }
+
+    pub fn add_assign_probe_cap_hit(&self) {
+        self.assign_probe_cap_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_promoted_processes(&self, cnt: u64) {
+        self.promoted_processes.fetch_add(cnt, Ordering::Relaxed);
+    }
+
+    pub fn add_schedule_retry(&self) {
+        self.schedule_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_processes_created(&self, cnt: u64) {
+        self.processes_created.fetch_add(cnt, Ordering::Relaxed);
+    }
+
+    pub fn add_process_assigned(&self, processing_mode: ProcessingMode) {
+        *self
+            .processes_assigned
+            .lock()
+            .unwrap()
+            .entry(processing_mode.to_string().to_ascii_lowercase())
+            .or_default() += 1;
+    }
+
+    pub fn add_assign_no_work(&self) {
+        self.assign_no_work.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            aged_out_processes: self.aged_out_processes.load(Ordering::Relaxed),
+            lock_retries: self.lock_retries.load(Ordering::Relaxed),
+            assign_requests: self.assign_requests.load(Ordering::Relaxed),
+            assign_probes: self.assign_probes.load(Ordering::Relaxed),
+            assign_probe_cap_hits: self.assign_probe_cap_hits.load(Ordering::Relaxed),
+            promoted_processes: self.promoted_processes.load(Ordering::Relaxed),
+            schedule_retries: self.schedule_retries.load(Ordering::Relaxed),
+            processes_created: self.processes_created.load(Ordering::Relaxed),
+            processes_assigned: self.processes_assigned.lock().unwrap().clone(),
+            assign_no_work: self.assign_no_work.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Dispatcher counters of `GET /stats`.
+#[derive(Serialize, Debug)]
+pub struct StatsSnapshot {
+    pub aged_out_processes: u64,
+    /// Claim attempts retried after a MySQL deadlock / lock wait timeout.
+    pub lock_retries: u64,
+    pub assign_requests: u64,
+    /// Candidate sources tried over all `assign_requests`.
+    pub assign_probes: u64,
+    /// Assign requests that gave up after `ASSIGN_MAX_PROBES` candidates; a
+    /// steadily growing count means candidates are never claimable (livelock).
+    pub assign_probe_cap_hits: u64,
+    /// `Created` processes moved to `Pending` by `promote_created_to_pending`.
+    pub promoted_processes: u64,
+    /// Schedule cycles re-run after a connection error (`SCHEDULE_RETRY_ATTEMPTS`).
+    pub schedule_retries: u64,
+    /// Processes created by `prepare_schedule`.
+    pub processes_created: u64,
+    /// Assigned processes (`/obtain_new_process` and by id) by processing mode.
+    pub processes_assigned: BTreeMap<String, u64>,
+    /// `assign_process` calls answered with `204`, whatever the reason.
+    pub assign_no_work: u64,
+}
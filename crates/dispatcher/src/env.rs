@@ -1,12 +1,50 @@
+use crate::cancellation_ext::CancellationLogLevel;
+use crate::db_repository::ProcessReadsPool;
+use crate::dispatcher::{NullCreatedAt, ScanStrategy};
+use crate::process_class::ProcessClasses;
+use chrono_tz::Tz;
 use std::env;
+use std::str::FromStr;
 pub struct EnvParams {
     http_port: u16,
     max_db_connections: u32,
+    pd_max_connections: u32,
+    mvp_max_connections: u32,
+    max_concurrent_claims: u32,
     mvp_db_url: String,
     pd_db_url: String,
+    pd_read_db_url: Option<String>,
+    process_reads_pool: ProcessReadsPool,
+    process_classes: ProcessClasses,
+    read_your_writes_secs: u64,
+    db_ping_query: String,
+    source_cache_ttl_secs: u64,
+    http_request_timeout_secs: Option<u64>,
+    max_process_age_secs: Option<u64>,
+    stale_process_secs: Option<u64>,
+    db_connect_retries: u32,
+    db_connect_base_delay_ms: u64,
+    stale_process_sweep_secs: u64,
+    ready_requires_schedule: bool,
+    export_max_rows: u32,
+    auto_migrate: bool,
+    cancellation_log_level: CancellationLogLevel,
+    lifecycle_events: bool,
+    assign_max_probes: u32,
+    max_creates_per_cycle: Option<u16>,
+    schedule_retry_attempts: u32,
+    schedule_retry_backoff_ms: u64,
+    admin_api_key: Option<String>,
+    error_is_terminal: bool,
+    scan_strategy: ScanStrategy,
+    null_created_at: NullCreatedAt,
+    timezone: Tz,
+    schedule_interval_secs: u64,
+    statsd_addr: Option<String>,
 }
 
 impl EnvParams {
+    /// Parameters with the `fetch_env_params` defaults for every optional variable.
     pub fn new(
         http_port: u16,
         max_db_connections: u32,
@@ -16,8 +54,39 @@ impl EnvParams {
         EnvParams {
             http_port,
             max_db_connections,
+            pd_max_connections: max_db_connections,
+            mvp_max_connections: max_db_connections,
+            max_concurrent_claims: (max_db_connections / 2).max(1),
             mvp_db_url,
             pd_db_url,
+            pd_read_db_url: None,
+            process_reads_pool: ProcessReadsPool::Pd,
+            process_classes: ProcessClasses::default(),
+            read_your_writes_secs: 5,
+            db_ping_query: "SELECT 1".to_string(),
+            source_cache_ttl_secs: 0,
+            http_request_timeout_secs: None,
+            max_process_age_secs: None,
+            stale_process_secs: None,
+            db_connect_retries: 5,
+            db_connect_base_delay_ms: 500,
+            stale_process_sweep_secs: 60,
+            ready_requires_schedule: false,
+            export_max_rows: 100_000,
+            auto_migrate: false,
+            cancellation_log_level: CancellationLogLevel::Info,
+            lifecycle_events: false,
+            assign_max_probes: 10,
+            max_creates_per_cycle: None,
+            schedule_retry_attempts: 3,
+            schedule_retry_backoff_ms: 500,
+            admin_api_key: None,
+            error_is_terminal: false,
+            scan_strategy: ScanStrategy::Fifo,
+            null_created_at: NullCreatedAt::Create,
+            timezone: chrono_tz::Europe::Berlin,
+            schedule_interval_secs: 5,
+            statsd_addr: None,
         }
     }
 
@@ -27,12 +96,150 @@ impl EnvParams {
     pub fn max_db_connections(&self) -> u32 {
         self.max_db_connections
     }
+    /// Size of the `pd` pools (primary and read replica).
+    pub fn pd_max_connections(&self) -> u32 {
+        self.pd_max_connections
+    }
+    pub fn mvp_max_connections(&self) -> u32 {
+        self.mvp_max_connections
+    }
+    /// Claiming HTTP requests served at once; the rest get `503`.
+    pub fn max_concurrent_claims(&self) -> u32 {
+        self.max_concurrent_claims
+    }
     pub fn mvp_db_url(&self) -> &str {
         &self.mvp_db_url
     }
     pub fn pd_db_url(&self) -> &str {
         &self.pd_db_url
     }
+    pub fn pd_read_db_url(&self) -> Option<&str> {
+        self.pd_read_db_url.as_deref()
+    }
+    pub fn process_reads_pool(&self) -> ProcessReadsPool {
+        self.process_reads_pool
+    }
+    pub fn process_classes(&self) -> &ProcessClasses {
+        &self.process_classes
+    }
+    pub fn read_your_writes_secs(&self) -> u64 {
+        self.read_your_writes_secs
+    }
+    pub fn db_ping_query(&self) -> &str {
+        &self.db_ping_query
+    }
+    /// Retries of a failed pool connect on startup, after the first attempt.
+    pub fn db_connect_retries(&self) -> u32 {
+        self.db_connect_retries
+    }
+    /// Delay before the first connect retry, doubled for every further one.
+    pub fn db_connect_base_delay_ms(&self) -> u64 {
+        self.db_connect_base_delay_ms
+    }
+    pub fn source_cache_ttl_secs(&self) -> u64 {
+        self.source_cache_ttl_secs
+    }
+    /// Max wall time of a non-claiming handler, `None` for no limit.
+    pub fn http_request_timeout_secs(&self) -> Option<u64> {
+        self.http_request_timeout_secs
+    }
+    pub fn max_process_age_secs(&self) -> Option<u64> {
+        self.max_process_age_secs
+    }
+    /// `Processing` processes not updated for longer than this are requeued.
+    pub fn stale_process_secs(&self) -> Option<u64> {
+        self.stale_process_secs
+    }
+    pub fn stale_process_sweep_secs(&self) -> u64 {
+        self.stale_process_sweep_secs
+    }
+    pub fn ready_requires_schedule(&self) -> bool {
+        self.ready_requires_schedule
+    }
+    pub fn export_max_rows(&self) -> u32 {
+        self.export_max_rows
+    }
+    pub fn auto_migrate(&self) -> bool {
+        self.auto_migrate
+    }
+    pub fn cancellation_log_level(&self) -> CancellationLogLevel {
+        self.cancellation_log_level
+    }
+    pub fn lifecycle_events(&self) -> bool {
+        self.lifecycle_events
+    }
+    pub fn assign_max_probes(&self) -> u32 {
+        self.assign_max_probes
+    }
+    pub fn max_creates_per_cycle(&self) -> Option<u16> {
+        self.max_creates_per_cycle
+    }
+    pub fn schedule_retry_attempts(&self) -> u32 {
+        self.schedule_retry_attempts
+    }
+    pub fn schedule_retry_backoff_ms(&self) -> u64 {
+        self.schedule_retry_backoff_ms
+    }
+    pub fn admin_api_key(&self) -> Option<&str> {
+        self.admin_api_key.as_deref()
+    }
+    pub fn error_is_terminal(&self) -> bool {
+        self.error_is_terminal
+    }
+    pub fn scan_strategy(&self) -> ScanStrategy {
+        self.scan_strategy
+    }
+    pub fn null_created_at(&self) -> NullCreatedAt {
+        self.null_created_at
+    }
+    /// Timezone of the calendar day behind "one process per source per day".
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+    /// Pause of the main loop between two schedule cycles.
+    pub fn schedule_interval_secs(&self) -> u64 {
+        self.schedule_interval_secs
+    }
+    /// StatsD agent `host:port`; only used with the `statsd` feature.
+    pub fn statsd_addr(&self) -> Option<&str> {
+        self.statsd_addr.as_deref()
+    }
+}
+
+#[cfg(test)]
+impl EnvParams {
+    /// Defaults of `fetch_env_params` for tests that do not touch a DB.
+    pub(crate) fn for_tests() -> EnvParams {
+        EnvParams {
+            schedule_retry_backoff_ms: 1,
+            ..EnvParams::new(8089, 10, String::new(), String::new())
+        }
+    }
+
+    pub(crate) fn with_process_classes(mut self, process_classes: ProcessClasses) -> EnvParams {
+        self.process_classes = process_classes;
+        self
+    }
+
+    pub(crate) fn with_null_created_at(mut self, null_created_at: NullCreatedAt) -> EnvParams {
+        self.null_created_at = null_created_at;
+        self
+    }
+
+    pub(crate) fn with_max_creates_per_cycle(mut self, max_creates: u16) -> EnvParams {
+        self.max_creates_per_cycle = Some(max_creates);
+        self
+    }
+
+    pub(crate) fn with_scan_strategy(mut self, scan_strategy: ScanStrategy) -> EnvParams {
+        self.scan_strategy = scan_strategy;
+        self
+    }
+
+    pub(crate) fn with_assign_max_probes(mut self, assign_max_probes: u32) -> EnvParams {
+        self.assign_max_probes = assign_max_probes;
+        self
+    }
 }
 
 pub fn fetch_env_params() -> EnvParams {
@@ -52,6 +259,35 @@ pub fn fetch_env_params() -> EnvParams {
         }
     };
 
+    let pd_max_connections: u32 = match env::var("PD_MAX_CONNECTIONS") {
+        Ok(cnt) => cnt.parse::<u32>().unwrap(),
+        Err(_) => {
+            println!("PD_MAX_CONNECTIONS is not set. Using MAX_DB_CONNECTIONS");
+            max_db_connections
+        }
+    };
+
+    let mvp_max_connections: u32 = match env::var("MVP_MAX_CONNECTIONS") {
+        Ok(cnt) => cnt.parse::<u32>().unwrap(),
+        Err(_) => {
+            println!("MVP_MAX_CONNECTIONS is not set. Using MAX_DB_CONNECTIONS");
+            max_db_connections
+        }
+    };
+
+    //half of the write pool, the other half stays free for the scheduler and status writes
+    let max_concurrent_claims: u32 = match env::var("MAX_CONCURRENT_CLAIMS") {
+        Ok(cnt) => cnt.parse::<u32>().unwrap(),
+        Err(_) => {
+            let cnt = (pd_max_connections / 2).max(1);
+            println!("MAX_CONCURRENT_CLAIMS is not set. Using PD_MAX_CONNECTIONS / 2 = {}", cnt);
+            cnt
+        }
+    };
+    if max_concurrent_claims == 0 {
+        panic!("MAX_CONCURRENT_CLAIMS must be at least 1");
+    }
+
     let mvp_db_url: String = match env::var("MVP_DATABASE_URL") {
         Ok(url) => url,
         Err(_) => {
@@ -65,5 +301,370 @@ pub fn fetch_env_params() -> EnvParams {
         }
     };
 
-    EnvParams::new(http_port, max_db_connections, mvp_db_url, pd_db_url)
+    let pd_read_db_url: Option<String> = match env::var("PD_READ_DATABASE_URL") {
+        Ok(url) => Some(url),
+        Err(_) => {
+            println!("PD_READ_DATABASE_URL is not set. Status reads use PD_DATABASE_URL");
+            None
+        }
+    };
+
+    let process_reads_pool: ProcessReadsPool = match env::var("PROCESS_READS_POOL") {
+        Ok(pool) => pool.parse::<ProcessReadsPool>().unwrap(),
+        Err(_) => {
+            println!("PROCESS_READS_POOL is not set. Using default pd");
+            ProcessReadsPool::Pd
+        }
+    };
+    if let Err(e) = validate_process_reads_pool(process_reads_pool, pd_read_db_url.is_some()) {
+        panic!("PROCESS_READS_POOL is invalid: {}", e);
+    }
+
+    let process_classes: ProcessClasses = match env::var("PROCESS_CLASSES") {
+        Ok(classes) => classes.parse::<ProcessClasses>().unwrap(),
+        Err(_) => {
+            println!("PROCESS_CLASSES is not set. Using a single default class");
+            ProcessClasses::default()
+        }
+    };
+    for class in process_classes.iter() {
+        let reads_pool = class.reads_pool.unwrap_or(process_reads_pool);
+        if let Err(e) = validate_process_reads_pool(reads_pool, pd_read_db_url.is_some()) {
+            panic!("PROCESS_CLASSES is invalid: class {}: {}", class.name, e);
+        }
+    }
+
+    let read_your_writes_secs: u64 = match env::var("READ_YOUR_WRITES_SECS") {
+        Ok(secs) => secs.parse::<u64>().unwrap(),
+        Err(_) => {
+            println!("READ_YOUR_WRITES_SECS is not set. Using default 5");
+            5
+        }
+    };
+
+    let db_ping_query: String = match env::var("DB_PING_QUERY") {
+        Ok(query) => query,
+        Err(_) => {
+            println!("DB_PING_QUERY is not set. Using default 'SELECT 1'");
+            "SELECT 1".to_string()
+        }
+    };
+    if let Err(e) = validate_ping_query(&db_ping_query) {
+        panic!("DB_PING_QUERY is invalid: {}", e);
+    }
+
+    let db_connect_retries: u32 = match env::var("DB_CONNECT_RETRIES") {
+        Ok(retries) => retries.parse::<u32>().unwrap(),
+        Err(_) => {
+            println!("DB_CONNECT_RETRIES is not set. Using default 5");
+            5
+        }
+    };
+
+    let db_connect_base_delay_ms: u64 = match env::var("DB_CONNECT_BASE_DELAY_MS") {
+        Ok(delay) => delay.parse::<u64>().unwrap(),
+        Err(_) => {
+            println!("DB_CONNECT_BASE_DELAY_MS is not set. Using default 500");
+            500
+        }
+    };
+
+    let source_cache_ttl_secs: u64 = match env::var("SOURCE_CACHE_TTL_SECS") {
+        Ok(secs) => secs.parse::<u64>().unwrap(),
+        Err(_) => {
+            println!("SOURCE_CACHE_TTL_SECS is not set. Using default 0 (cache disabled)");
+            0
+        }
+    };
+
+    let http_request_timeout_secs: Option<u64> = match env::var("HTTP_REQUEST_TIMEOUT_SECS") {
+        Ok(secs) => Some(secs.parse::<u64>().unwrap()),
+        Err(_) => {
+            println!("HTTP_REQUEST_TIMEOUT_SECS is not set. Handlers run without a time limit");
+            None
+        }
+    };
+    if http_request_timeout_secs == Some(0) {
+        panic!("HTTP_REQUEST_TIMEOUT_SECS must be at least 1");
+    }
+
+    let max_process_age_secs: Option<u64> = match env::var("MAX_PROCESS_AGE_SECS") {
+        Ok(secs) => Some(secs.parse::<u64>().unwrap()),
+        Err(_) => {
+            println!("MAX_PROCESS_AGE_SECS is not set. Processes never expire");
+            None
+        }
+    };
+
+    let stale_process_secs: Option<u64> = match env::var("STALE_PROCESS_SECS") {
+        Ok(secs) => Some(secs.parse::<u64>().unwrap()),
+        Err(_) => {
+            println!("STALE_PROCESS_SECS is not set. Stale processes are never requeued");
+            None
+        }
+    };
+
+    let stale_process_sweep_secs: u64 = match env::var("STALE_PROCESS_SWEEP_SECS") {
+        Ok(secs) => secs.parse::<u64>().unwrap(),
+        Err(_) => {
+            println!("STALE_PROCESS_SWEEP_SECS is not set. Using default 60");
+            60
+        }
+    };
+
+    let ready_requires_schedule: bool = match env::var("READY_REQUIRES_SCHEDULE") {
+        Ok(flag) => flag.parse::<bool>().unwrap(),
+        Err(_) => {
+            println!("READY_REQUIRES_SCHEDULE is not set. Using default false");
+            false
+        }
+    };
+
+    let export_max_rows: u32 = match env::var("EXPORT_MAX_ROWS") {
+        Ok(cnt) => cnt.parse::<u32>().unwrap(),
+        Err(_) => {
+            println!("EXPORT_MAX_ROWS is not set. Using default 100000");
+            100_000
+        }
+    };
+
+    let auto_migrate: bool = match env::var("AUTO_MIGRATE") {
+        Ok(flag) => flag.parse::<bool>().unwrap(),
+        Err(_) => {
+            println!("AUTO_MIGRATE is not set. Using default false");
+            false
+        }
+    };
+
+    let cancellation_log_level: CancellationLogLevel = match env::var("CANCELLATION_LOG_LEVEL") {
+        Ok(level) => level.parse::<CancellationLogLevel>().unwrap(),
+        Err(_) => {
+            println!("CANCELLATION_LOG_LEVEL is not set. Using default info");
+            CancellationLogLevel::Info
+        }
+    };
+
+    let lifecycle_events: bool = match env::var("LIFECYCLE_EVENTS") {
+        Ok(flag) => flag.parse::<bool>().unwrap(),
+        Err(_) => {
+            println!("LIFECYCLE_EVENTS is not set. Using default false");
+            false
+        }
+    };
+
+    let assign_max_probes: u32 = match env::var("ASSIGN_MAX_PROBES") {
+        Ok(cnt) => cnt.parse::<u32>().unwrap(),
+        Err(_) => {
+            println!("ASSIGN_MAX_PROBES is not set. Using default 10");
+            10
+        }
+    };
+
+    let max_creates_per_cycle: Option<u16> = match env::var("MAX_CREATES_PER_CYCLE") {
+        Ok(cnt) => Some(cnt.parse::<u16>().unwrap()),
+        Err(_) => {
+            println!("MAX_CREATES_PER_CYCLE is not set. Creations per cycle are not capped");
+            None
+        }
+    };
+
+    let schedule_retry_attempts: u32 = match env::var("SCHEDULE_RETRY_ATTEMPTS") {
+        Ok(cnt) => cnt.parse::<u32>().unwrap(),
+        Err(_) => {
+            println!("SCHEDULE_RETRY_ATTEMPTS is not set. Using default 3");
+            3
+        }
+    };
+
+    let schedule_retry_backoff_ms: u64 = match env::var("SCHEDULE_RETRY_BACKOFF_MS") {
+        Ok(ms) => ms.parse::<u64>().unwrap(),
+        Err(_) => {
+            println!("SCHEDULE_RETRY_BACKOFF_MS is not set. Using default 500");
+            500
+        }
+    };
+
+    let admin_api_key: Option<String> = match env::var("ADMIN_API_KEY") {
+        Ok(key) if !key.is_empty() => Some(key),
+        _ => {
+            println!("ADMIN_API_KEY is not set. Admin endpoints are disabled");
+            None
+        }
+    };
+
+    let error_is_terminal: bool = match env::var("ERROR_IS_TERMINAL") {
+        Ok(flag) => flag.parse::<bool>().unwrap(),
+        Err(_) => {
+            println!("ERROR_IS_TERMINAL is not set. Using default false");
+            false
+        }
+    };
+
+    let scan_strategy: ScanStrategy = match env::var("SCAN_STRATEGY") {
+        Ok(strategy) => strategy.parse::<ScanStrategy>().unwrap(),
+        Err(_) => {
+            println!("SCAN_STRATEGY is not set. Using default fifo");
+            ScanStrategy::Fifo
+        }
+    };
+
+    let null_created_at: NullCreatedAt = match env::var("NULL_CREATED_AT") {
+        Ok(handling) => handling.parse::<NullCreatedAt>().unwrap(),
+        Err(_) => {
+            println!("NULL_CREATED_AT is not set. Using default create");
+            NullCreatedAt::Create
+        }
+    };
+
+    let timezone: Tz = match env::var("DISPATCH_TIMEZONE") {
+        Ok(timezone) => match parse_timezone(&timezone) {
+            Ok(timezone) => timezone,
+            Err(e) => panic!("DISPATCH_TIMEZONE is invalid: {}", e),
+        },
+        Err(_) => {
+            println!("DISPATCH_TIMEZONE is not set. Using default Europe/Berlin");
+            chrono_tz::Europe::Berlin
+        }
+    };
+
+    let schedule_interval_secs: u64 = match env::var("SCHEDULE_INTERVAL_SECS") {
+        Ok(secs) => secs.parse::<u64>().unwrap(),
+        Err(_) => {
+            println!("SCHEDULE_INTERVAL_SECS is not set. Using default 5");
+            5
+        }
+    };
+
+    let statsd_addr: Option<String> = match env::var("STATSD_ADDR") {
+        Ok(addr) if !addr.is_empty() => Some(addr),
+        _ => {
+            println!("STATSD_ADDR is not set. StatsD metrics are disabled");
+            None
+        }
+    };
+
+    EnvParams {
+        http_port,
+        max_db_connections,
+        pd_max_connections,
+        mvp_max_connections,
+        max_concurrent_claims,
+        mvp_db_url,
+        pd_db_url,
+        pd_read_db_url,
+        process_reads_pool,
+        process_classes,
+        read_your_writes_secs,
+        db_ping_query,
+        source_cache_ttl_secs,
+        http_request_timeout_secs,
+        max_process_age_secs,
+        stale_process_secs,
+        db_connect_retries,
+        db_connect_base_delay_ms,
+        stale_process_sweep_secs,
+        ready_requires_schedule,
+        export_max_rows,
+        auto_migrate,
+        cancellation_log_level,
+        lifecycle_events,
+        assign_max_probes,
+        max_creates_per_cycle,
+        schedule_retry_attempts,
+        schedule_retry_backoff_ms,
+        admin_api_key,
+        error_is_terminal,
+        scan_strategy,
+        null_created_at,
+        timezone,
+        schedule_interval_secs,
+        statsd_addr,
+    }
+}
+
+/// IANA name, e.g. `Europe/Berlin` or `UTC`.
+fn parse_timezone(value: &str) -> Result<Tz, String> {
+    Tz::from_str(value.trim()).map_err(|_| format!("unknown timezone '{}'", value))
+}
+
+fn validate_process_reads_pool(
+    pool: ProcessReadsPool,
+    has_pd_read_db_url: bool,
+) -> Result<(), String> {
+    if pool == ProcessReadsPool::PdRead && !has_pd_read_db_url {
+        return Err("pd_read needs PD_READ_DATABASE_URL".to_string());
+    }
+    Ok(())
+}
+
+/// Accepts a single read-only statement (`SELECT` / `SHOW`), optionally prefixed
+/// with `/* ... */` comments as used by ProxySQL/Vitess routing hints.
+fn validate_ping_query(query: &str) -> Result<(), String> {
+    let mut rest = query.trim();
+    while let Some(comment) = rest.strip_prefix("/*") {
+        let end = comment
+            .find("*/")
+            .ok_or_else(|| "unterminated comment".to_string())?;
+        rest = comment[end + 2..].trim_start();
+    }
+
+    let statement = rest.strip_suffix(';').unwrap_or(rest).trim_end();
+    if statement.contains(';') {
+        return Err("multiple statements are not allowed".to_string());
+    }
+
+    let keyword = statement
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    if keyword != "SELECT" && keyword != "SHOW" {
+        return Err(format!("'{}' is not a read-only statement", query));
+    }
+
+    let upper = statement.to_ascii_uppercase();
+    if upper.contains(" INTO ") || upper.contains(" FOR UPDATE") || upper.contains(" FOR SHARE") {
+        return Err(format!("'{}' is not a read-only statement", query));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_query_accepts_read_only_statements() {
+        assert!(validate_ping_query("SELECT 1").is_ok());
+        assert!(validate_ping_query("select 1;").is_ok());
+        assert!(validate_ping_query("/* hostgroup=1 */ SELECT 1").is_ok());
+        assert!(validate_ping_query("SHOW STATUS LIKE 'Uptime'").is_ok());
+    }
+
+    #[test]
+    fn test_ping_query_rejects_writes() {
+        assert!(validate_ping_query("").is_err());
+        assert!(validate_ping_query("DELETE FROM sources").is_err());
+        assert!(validate_ping_query("SELECT 1; DROP TABLE sources").is_err());
+        assert!(validate_ping_query("SELECT 1 INTO @x").is_err());
+        assert!(validate_ping_query("SELECT * FROM sources FOR UPDATE").is_err());
+        assert!(validate_ping_query("/* unterminated SELECT 1").is_err());
+    }
+
+    #[test]
+    fn test_timezone_is_validated() {
+        assert_eq!(parse_timezone("Europe/Berlin"), Ok(chrono_tz::Europe::Berlin));
+        assert_eq!(parse_timezone("UTC"), Ok(chrono_tz::UTC));
+        assert!(parse_timezone("Europe/Atlantis").is_err());
+        assert!(parse_timezone("").is_err());
+    }
+
+    #[test]
+    fn test_process_reads_pool_misconfiguration() {
+        assert!("mvp".parse::<ProcessReadsPool>().is_err());
+        assert_eq!("PD_READ".parse::<ProcessReadsPool>(), Ok(ProcessReadsPool::PdRead));
+        assert!(validate_process_reads_pool(ProcessReadsPool::PdRead, false).is_err());
+        assert!(validate_process_reads_pool(ProcessReadsPool::PdRead, true).is_ok());
+        assert!(validate_process_reads_pool(ProcessReadsPool::Pd, false).is_ok());
+    }
 }
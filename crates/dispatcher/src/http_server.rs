@@ -1,17 +1,29 @@
+mod api_error;
+mod claim_permits;
+mod metrics;
 mod route_handlers;
 
 use crate::cancellation_ext::{CancellationError, CancellationExt};
 use crate::dispatcher::Dispatcher;
-use axum::routing::{get, patch};
-use axum::Router;
+use api_error::ApiError;
+use claim_permits::ClaimPermits;
+use axum::error_handling::HandleErrorLayer;
+use axum::routing::{get, patch, post};
+use axum::{BoxError, Router};
 use tracing::{info, warn};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio_util::sync::CancellationToken;
+use tower::ServiceBuilder;
 
 #[derive(Clone)]
 struct AppState {
     dispatcher: Arc<Dispatcher>,
+    cancellation_token: CancellationToken,
+    /// `ADMIN_API_KEY`; admin endpoints are disabled while it is unset.
+    admin_api_key: Option<String>,
+    claim_permits: Arc<ClaimPermits>,
 }
 
 #[derive(Debug)]
@@ -32,21 +44,25 @@ impl From<std::io::Error> for HttpServerError {
     }
 }
 
+/// Serves until the token is cancelled. Fails if the port cannot be bound
+/// (e.g. `AddrInUse`), so the caller can exit instead of running without an API.
 pub async fn start_http_server(
     http_port: u16,
     dispatcher: Arc<Dispatcher>,
+    request_timeout: Option<Duration>,
+    admin_api_key: Option<String>,
+    max_concurrent_claims: u32,
     cancellation_token: &CancellationToken,
-) {
-    let router = Router::new()
-        .route(
-            "/obtain_new_process/{supervisor_id}",
-            get(route_handlers::obtain_new_process_handler),
-        )
-        .route(
-            "/report_process_finish/{process_id}",
-            patch(route_handlers::report_process_finish_handler),
-        )
-        .with_state(Arc::new(AppState { dispatcher }));
+) -> Result<(), std::io::Error> {
+    let router = app_router(
+        Arc::new(AppState {
+            dispatcher,
+            cancellation_token: cancellation_token.clone(),
+            admin_api_key,
+            claim_permits: Arc::new(ClaimPermits::new(max_concurrent_claims)),
+        }),
+        request_timeout,
+    );
     let addr = SocketAddr::from(([0, 0, 0, 0], http_port));
     println!("listening on {}", addr);
 
@@ -57,12 +73,9 @@ pub async fn start_http_server(
         Ok(l) => l,
         Err(HttpServerError::Cancelled) => {
             info!("HTTP server bind cancelled");
-            return;
-        }
-        Err(HttpServerError::Io(e)) => {
-            warn!("Failed to bind HTTP server: {}", e);
-            return;
+            return Ok(());
         }
+        Err(HttpServerError::Io(e)) => return Err(e),
     };
 
     let shutdown_token = cancellation_token.clone();
@@ -79,4 +92,411 @@ pub async fn start_http_server(
     }
 
     info!("HTTP server shutdown completed");
+    Ok(())
+}
+
+/// The claiming routes are never cut by `request_timeout`: dropping one after its
+/// UPDATE committed would leave a process assigned to a supervisor that got `504`.
+fn app_router(state: Arc<AppState>, request_timeout: Option<Duration>) -> Router {
+    let claim_routes = Router::new()
+        .route(
+            "/obtain_new_process/{supervisor_id}",
+            get(route_handlers::obtain_new_process_handler),
+        )
+        .route(
+            "/assign_processes/{supervisor_id}",
+            post(route_handlers::assign_processes_handler),
+        )
+        .route(
+            "/assign_process/{supervisor_id}/by_id/{process_id}",
+            post(route_handlers::assign_process_by_id_handler),
+        );
+    let routes = Router::new()
+        .route(
+            "/report_process_finish/{process_id}",
+            patch(route_handlers::report_process_finish_handler),
+        )
+        .route(
+            "/process/{process_id}",
+            get(route_handlers::process_status_handler),
+        )
+        .route("/processes", get(route_handlers::list_processes_handler))
+        .route(
+            "/process/{process_id}/position",
+            get(route_handlers::queue_position_handler),
+        )
+        .route(
+            "/process/{process_id}/rerun",
+            post(route_handlers::rerun_process_handler),
+        )
+        .route(
+            "/processes/{process_id}/release",
+            post(route_handlers::release_process_handler),
+        )
+        .route(
+            "/processes/{process_id}/state",
+            post(route_handlers::update_process_state_handler),
+        )
+        .route(
+            "/supervisor/{supervisor_id}/reset",
+            post(route_handlers::reset_supervisor_handler),
+        )
+        .route("/ready", get(route_handlers::ready_handler))
+        .route("/health", get(route_handlers::health_handler))
+        .route("/export.csv", get(route_handlers::export_csv_handler))
+        .route("/info", get(route_handlers::info_handler))
+        .route("/metrics", get(route_handlers::metrics_handler))
+        .route("/stats", get(route_handlers::stats_handler))
+        .route("/stats/latency", get(route_handlers::latency_stats_handler))
+        .route(
+            "/sources/{source_id}/processes",
+            post(route_handlers::create_process_handler),
+        )
+        .route(
+            "/sources/refresh",
+            post(route_handlers::refresh_sources_handler),
+        )
+        .route(
+            "/drain_and_exit",
+            post(route_handlers::drain_and_exit_handler),
+        )
+        .route(
+            "/drain_and_exit/status",
+            get(route_handlers::drain_status_handler),
+        );
+    let routes = match request_timeout {
+        Some(request_timeout) => with_request_timeout(routes, request_timeout),
+        None => routes,
+    };
+    claim_routes.merge(routes).with_state(state)
+}
+
+/// Caps the total wall time of a handler, independently of per-query DB limits.
+fn with_request_timeout<S>(router: Router<S>, request_timeout: Duration) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(handle_request_timeout_error))
+            .timeout(request_timeout),
+    )
+}
+
+async fn handle_request_timeout_error(err: BoxError) -> ApiError {
+    if err.is::<tower::timeout::error::Elapsed>() {
+        warn!("HTTP request timed out");
+        ApiError::Timeout("Request timed out".to_string())
+    } else {
+        ApiError::Internal(format!("Unhandled internal error: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use crate::env::EnvParams;
+    use crate::process_store::in_memory::InMemoryProcessStore;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    fn test_router(dispatcher: Arc<Dispatcher>, admin_api_key: Option<&str>) -> Router {
+        app_router(
+            Arc::new(AppState {
+                dispatcher,
+                cancellation_token: CancellationToken::new(),
+                admin_api_key: admin_api_key.map(str::to_string),
+                claim_permits: Arc::new(ClaimPermits::new(1)),
+            }),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_port_in_use_is_an_error() {
+        let taken = std::net::TcpListener::bind(("0.0.0.0", 0)).unwrap();
+        let dispatcher = Dispatcher::new(
+            &EnvParams::for_tests(),
+            Arc::new(InMemoryProcessStore::new(vec![])),
+        );
+
+        let res = start_http_server(
+            taken.local_addr().unwrap().port(),
+            Arc::new(dispatcher),
+            Some(Duration::from_secs(5)),
+            None,
+            1,
+            &CancellationToken::new(),
+        )
+        .await;
+
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::AddrInUse);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_assigned_processes() {
+        let dispatcher = Arc::new(Dispatcher::new(
+            &EnvParams::for_tests(),
+            Arc::new(InMemoryProcessStore::new(vec![1])),
+        ));
+        dispatcher
+            .prepare_schedule(&CancellationToken::new())
+            .await
+            .unwrap();
+        let router = test_router(dispatcher.clone(), None);
+
+        let assign_uri = format!("/obtain_new_process/{}", uuid::Uuid::new_v4());
+        for _ in 0..2 {
+            let response = router
+                .clone()
+                .oneshot(Request::get(assign_uri.as_str()).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert!(response.status().is_success());
+        }
+        let response = router
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("dispatcher_processes_created_total 1\n"));
+        assert!(body.contains("dispatcher_processes_assigned_total{mode=\"regular\"} 1\n"));
+        assert!(body.contains("dispatcher_assign_no_work_total 1\n"));
+        let stats = dispatcher.stats();
+        assert!(stats.assign_probes > 0);
+        let assign_probes = format!("dispatcher_assign_probes_total {}\n", stats.assign_probes);
+        assert!(body.contains(&assign_probes));
+        assert!(body.contains("dispatcher_assign_probe_cap_hits_total 0\n"));
+        assert!(body.contains("dispatcher_schedule_retries_total 0\n"));
+        assert!(body.contains("dispatcher_aged_out_processes_total 0\n"));
+        assert!(body.contains("dispatcher_promoted_processes_total 0\n"));
+        assert!(body.contains("# TYPE dispatcher_source_locks gauge\n"));
+        assert!(body.contains("# TYPE dispatcher_process_latency_seconds histogram\n"));
+    }
+
+    #[tokio::test]
+    async fn test_db_error_renders_as_json_500_and_no_work_as_204() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![]));
+        store.fail_source_ids_lookups(1);
+        let router = test_router(Arc::new(Dispatcher::new(&EnvParams::for_tests(), store)), None);
+
+        let response = router
+            .clone()
+            .oneshot(Request::post("/sources/refresh").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "db_error");
+        assert!(body["message"].is_string());
+
+        let assign_uri = format!("/obtain_new_process/{}", uuid::Uuid::new_v4());
+        let response = router
+            .oneshot(Request::get(assign_uri.as_str()).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers()[shared::ASSIGN_REASON_HEADER], "no_sources");
+    }
+
+    #[tokio::test]
+    async fn test_no_work_reason_header() {
+        let dispatcher = Arc::new(Dispatcher::new(
+            &EnvParams::for_tests(),
+            Arc::new(InMemoryProcessStore::new(vec![])),
+        ));
+        let router = test_router(dispatcher.clone(), None);
+        let assign_uri = format!("/obtain_new_process/{}", uuid::Uuid::new_v4());
+
+        let response = router
+            .clone()
+            .oneshot(Request::get(assign_uri.as_str()).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[shared::ASSIGN_REASON_HEADER],
+            shared::ASSIGN_REASON_NO_SOURCES
+        );
+
+        dispatcher
+            .clone()
+            .start_drain_and_exit(Duration::from_secs(60), CancellationToken::new())
+            .unwrap();
+        let response = router
+            .oneshot(Request::get(assign_uri.as_str()).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[shared::ASSIGN_REASON_HEADER],
+            shared::ASSIGN_REASON_DRAINING
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assign_batch_rejects_zero_count_and_returns_empty_array() {
+        let router = test_router(
+            Arc::new(Dispatcher::new(
+                &EnvParams::for_tests(),
+                Arc::new(InMemoryProcessStore::new(vec![])),
+            )),
+            None,
+        );
+        let supervisor_id = uuid::Uuid::new_v4();
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post(format!("/assign_processes/{}?count=0", supervisor_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = router
+            .oneshot(
+                Request::post(format!("/assign_processes/{}?count=5", supervisor_id))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[shared::ASSIGN_REASON_HEADER],
+            shared::ASSIGN_REASON_NO_SOURCES
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_export_requires_the_admin_api_key() {
+        let dispatcher = Arc::new(Dispatcher::new(
+            &EnvParams::for_tests(),
+            Arc::new(InMemoryProcessStore::new(vec![1])),
+        ));
+
+        let response = test_router(dispatcher.clone(), None)
+            .oneshot(Request::get("/export.csv").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let router = test_router(dispatcher, Some("secret"));
+        let response = router
+            .clone()
+            .oneshot(Request::get("/export.csv").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = Request::get("/export.csv")
+            .header("X-Api-Key", "secret")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_drain_requires_the_admin_api_key() {
+        let dispatcher = Arc::new(Dispatcher::new(
+            &EnvParams::for_tests(),
+            Arc::new(InMemoryProcessStore::new(vec![1])),
+        ));
+
+        let response = test_router(dispatcher.clone(), None)
+            .oneshot(Request::post("/drain_and_exit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = test_router(dispatcher.clone(), Some("secret"))
+            .oneshot(Request::post("/drain_and_exit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(dispatcher.drain_status(), crate::dispatcher::DrainStatus::Idle);
+    }
+
+    #[tokio::test]
+    async fn test_list_processes_rejects_bad_state_limit_and_offset() {
+        let store = Arc::new(InMemoryProcessStore::new(vec![1]));
+        let router = test_router(Arc::new(Dispatcher::new(&EnvParams::for_tests(), store)), None);
+
+        for uri in [
+            "/processes?state=bogus",
+            "/processes?limit=201",
+            "/processes?offset=10001",
+        ] {
+            let response = router
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"], "bad_request");
+        }
+
+        let response = router
+            .oneshot(Request::get("/processes?state=created").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_slow_handler_times_out_with_504() {
+        let router = Router::new().route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "done"
+            }),
+        );
+        let router = with_request_timeout(router, Duration::from_millis(20));
+
+        let response = router
+            .oneshot(Request::get("/slow").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_fast_handler_is_not_affected() {
+        let router = Router::new().route("/fast", get(|| async { "done" }));
+        let router = with_request_timeout(router, Duration::from_secs(5));
+
+        let response = router
+            .oneshot(Request::get("/fast").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
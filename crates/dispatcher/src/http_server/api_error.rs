@@ -0,0 +1,168 @@
+use crate::dispatcher::{
+    AssignByIdError, CreateProcessError, DispatcherError, ReportFinishError, RerunError,
+};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+
+pub(super) const DRAINING_MESSAGE: &str = "Dispatcher is draining, no new processes are assigned";
+
+/// Error of an HTTP handler, rendered as its status code and a
+/// `{"error": <code>, "message": <text>}` body.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    Unavailable(String),
+    Timeout(String),
+    Db(sqlx::Error),
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Db(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable machine-readable code of the `error` field.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Unavailable(_) => "unavailable",
+            ApiError::Timeout(_) => "timeout",
+            ApiError::Db(_) => "db_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::Conflict(message)
+            | ApiError::Unavailable(message)
+            | ApiError::Timeout(message)
+            | ApiError::Internal(message) => write!(f, "{}", message),
+            ApiError::Db(e) => write!(f, "db error: {}", e),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "error": self.code(),
+            "message": self.to_string(),
+        });
+        (self.status_code(), Json(body)).into_response()
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> Self {
+        ApiError::Db(e)
+    }
+}
+
+impl From<DispatcherError> for ApiError {
+    fn from(e: DispatcherError) -> Self {
+        match e {
+            DispatcherError::DbError(e) => ApiError::Db(e),
+            DispatcherError::TerminatingSignalReceived => {
+                ApiError::Unavailable("Dispatcher is shutting down".to_string())
+            }
+            DispatcherError::InvalidState(_) => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<AssignByIdError> for ApiError {
+    fn from(e: AssignByIdError) -> Self {
+        match e {
+            AssignByIdError::NotFound(id) => {
+                ApiError::NotFound(format!("process {} not found", id))
+            }
+            AssignByIdError::NotClaimable(id) => {
+                ApiError::Conflict(format!("process {} is already assigned or finished", id))
+            }
+            AssignByIdError::Draining => ApiError::Unavailable(DRAINING_MESSAGE.to_string()),
+            AssignByIdError::InvalidState(_) => ApiError::Internal(e.to_string()),
+            AssignByIdError::Db(e) => ApiError::Db(e),
+        }
+    }
+}
+
+impl From<RerunError> for ApiError {
+    fn from(e: RerunError) -> Self {
+        match e {
+            RerunError::NotFound(id) => ApiError::NotFound(format!("process {} not found", id)),
+            RerunError::NotFinished(..)
+            | RerunError::SourceBusy(..)
+            | RerunError::ModeConflict(..) => {
+                ApiError::Conflict(e.to_string())
+            }
+            RerunError::InvalidState(_) => ApiError::Internal(e.to_string()),
+            RerunError::Db(e) => ApiError::Db(e),
+        }
+    }
+}
+
+impl From<CreateProcessError> for ApiError {
+    fn from(e: CreateProcessError) -> Self {
+        match e {
+            CreateProcessError::ModeConflict(..) => ApiError::Conflict(e.to_string()),
+            CreateProcessError::Db(e) => ApiError::Db(e),
+        }
+    }
+}
+
+impl From<ReportFinishError> for ApiError {
+    fn from(e: ReportFinishError) -> Self {
+        match e {
+            ReportFinishError::InvalidResult(_) | ReportFinishError::NotFinishedState(_) => {
+                ApiError::BadRequest(e.to_string())
+            }
+            ReportFinishError::NotFound(_) => ApiError::NotFound(e.to_string()),
+            ReportFinishError::AlreadyFinished(..) => ApiError::Conflict(e.to_string()),
+            ReportFinishError::Db(e) => ApiError::Db(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_error_body_shape() {
+        let response = ApiError::Db(sqlx::Error::PoolTimedOut).into_response();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "db_error");
+        assert!(body["message"].as_str().unwrap().starts_with("db error: "));
+    }
+}
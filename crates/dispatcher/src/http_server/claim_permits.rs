@@ -0,0 +1,72 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Admission control shared by every handler that claims a process in a `pd`
+/// transaction (`MAX_CONCURRENT_CLAIMS`). A request that finds no free permit
+/// is rejected instead of queueing for a pool connection.
+pub(super) struct ClaimPermits {
+    semaphore: Arc<Semaphore>,
+    max: u32,
+    rejected: AtomicU64,
+}
+
+/// Claim admission counters, part of `GET /stats`.
+#[derive(Serialize, Debug)]
+pub(super) struct ClaimPermitsSnapshot {
+    pub max_concurrent_claims: u32,
+    pub claims_in_flight: u32,
+    /// Claiming requests answered with `503` because no permit was free.
+    pub claims_rejected: u64,
+}
+
+impl ClaimPermits {
+    pub fn new(max: u32) -> Self {
+        ClaimPermits {
+            semaphore: Arc::new(Semaphore::new(max as usize)),
+            max,
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// The permit is held until the claim transaction is done; `None` means
+    /// the limit is reached and the caller should answer `503`.
+    pub fn try_acquire(&self) -> Option<OwnedSemaphorePermit> {
+        match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> ClaimPermitsSnapshot {
+        ClaimPermitsSnapshot {
+            max_concurrent_claims: self.max,
+            claims_in_flight: self.max - self.semaphore.available_permits() as u32,
+            claims_rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claims_over_the_limit_are_rejected() {
+        let permits = ClaimPermits::new(2);
+
+        let first = permits.try_acquire().unwrap();
+        let _second = permits.try_acquire().unwrap();
+        assert!(permits.try_acquire().is_none());
+        assert_eq!(permits.snapshot().claims_in_flight, 2);
+        assert_eq!(permits.snapshot().claims_rejected, 1);
+
+        drop(first);
+        assert!(permits.try_acquire().is_some());
+        assert_eq!(permits.snapshot().claims_in_flight, 1);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::dispatcher::{LatencyHistogram, StatsSnapshot, LATENCY_BUCKET_BOUNDS_SECS};
+use crate::http_server::claim_permits::ClaimPermitsSnapshot;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// `Content-Type` of the Prometheus text exposition format.
+pub(super) const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Renders the `GET /metrics` body from the same counters as `GET /stats`.
+pub(super) fn render_metrics(
+    stats: &StatsSnapshot,
+    claims: &ClaimPermitsSnapshot,
+    source_locks: usize,
+    latency_histograms: &BTreeMap<u32, LatencyHistogram>,
+) -> String {
+    let mut out = PrometheusText::default();
+    out.counter(
+        "dispatcher_processes_created_total",
+        "Processes created by prepare_schedule.",
+        stats.processes_created,
+    );
+    out.labeled_counter(
+        "dispatcher_processes_assigned_total",
+        "Processes assigned to a supervisor, by processing mode.",
+        "mode",
+        &stats.processes_assigned,
+    );
+    out.counter(
+        "dispatcher_assign_requests_total",
+        "assign_process calls.",
+        stats.assign_requests,
+    );
+    out.counter(
+        "dispatcher_assign_no_work_total",
+        "assign_process calls that returned no work.",
+        stats.assign_no_work,
+    );
+    out.counter(
+        "dispatcher_assign_probes_total",
+        "Candidate sources tried over all assign_process calls.",
+        stats.assign_probes,
+    );
+    out.counter(
+        "dispatcher_assign_probe_cap_hits_total",
+        "assign_process calls that gave up after ASSIGN_MAX_PROBES candidates.",
+        stats.assign_probe_cap_hits,
+    );
+    out.counter(
+        "dispatcher_schedule_retries_total",
+        "Schedule cycles re-run after a connection error.",
+        stats.schedule_retries,
+    );
+    out.counter(
+        "dispatcher_aged_out_processes_total",
+        "Processes aged out by MAX_PROCESS_AGE_SECS.",
+        stats.aged_out_processes,
+    );
+    out.counter(
+        "dispatcher_promoted_processes_total",
+        "Created processes moved to Pending.",
+        stats.promoted_processes,
+    );
+    out.counter(
+        "dispatcher_lock_retries_total",
+        "Claims retried after a MySQL deadlock or lock wait timeout.",
+        stats.lock_retries,
+    );
+    out.counter(
+        "dispatcher_claims_rejected_total",
+        "Claiming requests answered with 503 at MAX_CONCURRENT_CLAIMS.",
+        claims.claims_rejected,
+    );
+    out.gauge(
+        "dispatcher_claims_in_flight",
+        "Claim permits in use.",
+        u64::from(claims.claims_in_flight),
+    );
+    out.gauge(
+        "dispatcher_source_locks",
+        "Per-source locks currently held or awaited.",
+        source_locks as u64,
+    );
+    out.histogram(
+        "dispatcher_process_latency_seconds",
+        "Created to finished latency of processes, by source_id % 16.",
+        "source_bucket",
+        latency_histograms,
+    );
+    out.0
+}
+
+#[derive(Default)]
+struct PrometheusText(String);
+
+impl PrometheusText {
+    fn header(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.0, "# HELP {} {}", name, help);
+        let _ = writeln!(self.0, "# TYPE {} {}", name, kind);
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: u64) {
+        self.header(name, "counter", help);
+        let _ = writeln!(self.0, "{} {}", name, value);
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: u64) {
+        self.header(name, "gauge", help);
+        let _ = writeln!(self.0, "{} {}", name, value);
+    }
+
+    /// Label values are internal identifiers (e.g. `regular`), never escaped.
+    fn labeled_counter(
+        &mut self,
+        name: &str,
+        help: &str,
+        label: &str,
+        values: &BTreeMap<String, u64>,
+    ) {
+        self.header(name, "counter", help);
+        for (label_value, value) in values {
+            let _ = writeln!(self.0, "{}{{{}=\"{}\"}} {}", name, label, label_value, value);
+        }
+    }
+
+    fn histogram(
+        &mut self,
+        name: &str,
+        help: &str,
+        label: &str,
+        histograms: &BTreeMap<u32, LatencyHistogram>,
+    ) {
+        self.header(name, "histogram", help);
+        for (label_value, histogram) in histograms {
+            let mut cumulative = 0;
+            for (i, count) in histogram.bucket_counts.iter().enumerate() {
+                cumulative += count;
+                let le = match LATENCY_BUCKET_BOUNDS_SECS.get(i) {
+                    Some(bound) => bound.to_string(),
+                    None => "+Inf".to_string(),
+                };
+                let _ = writeln!(
+                    self.0,
+                    "{}_bucket{{{}=\"{}\",le=\"{}\"}} {}",
+                    name, label, label_value, le, cumulative
+                );
+            }
+            let labels = format!("{{{}=\"{}\"}}", label, label_value);
+            let _ = writeln!(self.0, "{}_sum{} {}", name, labels, histogram.sum_secs);
+            let _ = writeln!(self.0, "{}_count{} {}", name, labels, histogram.count);
+        }
+    }
+}
@@ -1,74 +1,545 @@
-use crate::dispatcher::ReportFinishError;
+use crate::dispatcher::{
+    AssignOutcome, BatchStopReason, ExportedProcess, NoWorkReason, StatsSnapshot,
+};
+use crate::http_server::api_error::{ApiError, DRAINING_MESSAGE};
+use crate::http_server::claim_permits::ClaimPermitsSnapshot;
+use crate::http_server::metrics::{render_metrics, PROMETHEUS_CONTENT_TYPE};
 use crate::http_server::AppState;
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use crate::process_class::parse_processing_mode;
+use crate::process_store::ProcessFilter;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::Json;
-use shared::ProcessFinishReport;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use shared::{
+    CreateProcessRequest, DispatchState, ProcessFinishReport, ProcessStateUpdate, ProcessingMode,
+    ASSIGN_REASON_HEADER,
+};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OwnedSemaphorePermit;
 use uuid::Uuid;
 
 pub async fn obtain_new_process_handler(
     State(state): State<Arc<AppState>>,
     Path(supervisor_id): Path<Uuid>,
-) -> impl IntoResponse {
-    let res = state.dispatcher.assign_process(supervisor_id).await;
+) -> Result<Response, ApiError> {
+    let _claim_permit = acquire_claim_permit(&state)?;
+    match state.dispatcher.assign_process(supervisor_id).await? {
+        AssignOutcome::Assigned(assigned_process) => {
+            Ok((StatusCode::OK, Json(serde_json::json!(assigned_process))).into_response())
+        }
+        AssignOutcome::NoWork(reason) => Ok((
+            StatusCode::NO_CONTENT,
+            [(ASSIGN_REASON_HEADER, reason.as_str())],
+        )
+            .into_response()),
+    }
+}
 
-    if res.is_err() {
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({
-                "message": format!("Failed to obtain new process: {}", res.unwrap_err())
-            })),
-        );
+const ASSIGN_BATCH_DEFAULT_COUNT: u32 = 1;
+const ASSIGN_BATCH_MAX_COUNT: u32 = 50;
+
+#[derive(Deserialize)]
+pub struct AssignBatchParams {
+    count: Option<u32>,
+}
+
+pub async fn assign_processes_handler(
+    State(state): State<Arc<AppState>>,
+    Path(supervisor_id): Path<Uuid>,
+    Query(params): Query<AssignBatchParams>,
+) -> Result<Response, ApiError> {
+    let count = params.count.unwrap_or(ASSIGN_BATCH_DEFAULT_COUNT);
+    if count == 0 {
+        return Err(ApiError::BadRequest("count must be at least 1".to_string()));
     }
-    let assigned_process_option = res.unwrap();
-    if assigned_process_option.is_none() {
-        return (
-            StatusCode::NO_CONTENT,
-            Json(serde_json::json!({
-                "message": "No available processes to assign"
-            })),
+    //one permit for the whole batch, its claims run one after another
+    let _claim_permit = acquire_claim_permit(&state)?;
+    let count = count.min(ASSIGN_BATCH_MAX_COUNT);
+    let batch = state
+        .dispatcher
+        .assign_processes(supervisor_id, count)
+        .await?;
+    if batch.processes.is_empty()
+        && batch.stop_reason == Some(BatchStopReason::NoWork(NoWorkReason::Draining))
+    {
+        return Err(ApiError::Unavailable(DRAINING_MESSAGE.to_string()));
+    }
+    let mut response = (StatusCode::OK, Json(serde_json::json!(batch.processes))).into_response();
+    if let Some(reason) = batch.stop_reason {
+        response.headers_mut().insert(
+            ASSIGN_REASON_HEADER,
+            header::HeaderValue::from_static(reason.as_str()),
         );
     }
+    Ok(response)
+}
 
-    (
-        StatusCode::OK,
-        Json(serde_json::json!(assigned_process_option.unwrap())),
-    )
+pub async fn assign_process_by_id_handler(
+    State(state): State<Arc<AppState>>,
+    Path((supervisor_id, process_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let _claim_permit = acquire_claim_permit(&state)?;
+    let assigned_process = state
+        .dispatcher
+        .assign_process_by_id(supervisor_id, process_id)
+        .await?;
+    Ok((StatusCode::OK, Json(serde_json::json!(assigned_process))))
+}
+
+pub async fn release_process_handler(
+    State(state): State<Arc<AppState>>,
+    Path(process_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !state.dispatcher.release_process(process_id).await? {
+        return Err(ApiError::NotFound(format!(
+            "process {} not found or already finished",
+            process_id
+        )));
+    }
+    Ok((StatusCode::OK, Json(serde_json::json!({ "message": "ok" }))))
+}
+
+fn acquire_claim_permit(state: &AppState) -> Result<OwnedSemaphorePermit, ApiError> {
+    state
+        .claim_permits
+        .try_acquire()
+        .ok_or_else(|| ApiError::Unavailable("Too many concurrent claims, retry later".to_string()))
+}
+
+pub async fn process_status_handler(
+    State(state): State<Arc<AppState>>,
+    Path(process_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let process_status = state
+        .dispatcher
+        .process_status(process_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("process {} not found", process_id)))?;
+    Ok((StatusCode::OK, Json(serde_json::json!(process_status))))
+}
+
+const PROCESS_LIST_DEFAULT_LIMIT: u32 = 50;
+const PROCESS_LIST_MAX_LIMIT: u32 = 200;
+/// Deeper pages make MySQL read and drop every skipped row; filter instead.
+const PROCESS_LIST_MAX_OFFSET: u32 = 10_000;
+
+#[derive(Deserialize)]
+pub struct ListProcessesParams {
+    source_id: Option<u32>,
+    state: Option<String>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+pub async fn list_processes_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ListProcessesParams>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = params.limit.unwrap_or(PROCESS_LIST_DEFAULT_LIMIT);
+    if limit > PROCESS_LIST_MAX_LIMIT {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be at most {}",
+            PROCESS_LIST_MAX_LIMIT
+        )));
+    }
+    let offset = params.offset.unwrap_or(0);
+    if offset > PROCESS_LIST_MAX_OFFSET {
+        return Err(ApiError::BadRequest(format!(
+            "offset must be at most {}",
+            PROCESS_LIST_MAX_OFFSET
+        )));
+    }
+    let dispatch_state = params
+        .state
+        .as_deref()
+        .map(DispatchState::try_from)
+        .transpose()
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let filter = ProcessFilter {
+        source_id: params.source_id,
+        state: dispatch_state,
+        limit,
+        offset,
+    };
+    let processes = state.dispatcher.list_processes(&filter).await?;
+    Ok((StatusCode::OK, Json(serde_json::json!(processes))))
+}
+
+pub async fn queue_position_handler(
+    State(state): State<Arc<AppState>>,
+    Path(process_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let queue_position = state
+        .dispatcher
+        .queue_position(process_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("process {} not found", process_id)))?;
+    Ok((StatusCode::OK, Json(serde_json::json!(queue_position))))
+}
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Admin endpoints require `X-Api-Key: <ADMIN_API_KEY>` and are disabled
+/// while no key is configured.
+fn check_admin_api_key(admin_api_key: Option<&str>, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(admin_api_key) = admin_api_key else {
+        return Err(ApiError::Forbidden(
+            "Admin endpoints are disabled, ADMIN_API_KEY is not set".to_string(),
+        ));
+    };
+    match headers.get(API_KEY_HEADER) {
+        Some(key) if key.as_bytes() == admin_api_key.as_bytes() => Ok(()),
+        _ => Err(ApiError::Unauthorized(
+            "Invalid or missing API key".to_string(),
+        )),
+    }
+}
+
+pub async fn rerun_process_handler(
+    State(state): State<Arc<AppState>>,
+    Path(process_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    check_admin_api_key(state.admin_api_key.as_deref(), &headers)?;
+    let new_process_id = state.dispatcher.rerun_process(process_id).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({ "process_id": new_process_id })),
+    ))
+}
+
+pub async fn create_process_handler(
+    State(state): State<Arc<AppState>>,
+    Path(source_id): Path<u32>,
+    headers: HeaderMap,
+    Json(request): Json<CreateProcessRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    check_admin_api_key(state.admin_api_key.as_deref(), &headers)?;
+    let mode = match request.mode.as_deref() {
+        Some(mode) => parse_processing_mode(mode).map_err(ApiError::BadRequest)?,
+        None => ProcessingMode::Regular,
+    };
+    let process_id = state.dispatcher.create_process(source_id, mode).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({ "process_id": process_id })),
+    ))
 }
 
 pub async fn report_process_finish_handler(
     State(state): State<Arc<AppState>>,
     Path(process_id): Path<Uuid>,
     Json(report): Json<ProcessFinishReport>,
-) -> impl IntoResponse {
-    match state
+) -> Result<impl IntoResponse, ApiError> {
+    state
         .dispatcher
         .report_process_finish(process_id, &report.result)
-        .await
-    {
-        Ok(()) => (
-            StatusCode::OK,
-            Json(serde_json::json!({ "message": "ok" })),
-        ),
-        Err(ReportFinishError::InvalidResult(value)) => (
-            StatusCode::BAD_REQUEST,
-            Json(serde_json::json!({
-                "message": format!("invalid result value '{}'", value)
-            })),
-        ),
-        Err(ReportFinishError::NotFound(id)) => (
-            StatusCode::NOT_FOUND,
+        .await?;
+    Ok((StatusCode::OK, Json(serde_json::json!({ "message": "ok" }))))
+}
+
+pub async fn update_process_state_handler(
+    State(state): State<Arc<AppState>>,
+    Path(process_id): Path<Uuid>,
+    Json(update): Json<ProcessStateUpdate>,
+) -> Result<impl IntoResponse, ApiError> {
+    state
+        .dispatcher
+        .update_process_state(process_id, &update.state)
+        .await?;
+    Ok((StatusCode::OK, Json(serde_json::json!({ "message": "ok" }))))
+}
+
+pub async fn reset_supervisor_handler(
+    State(state): State<Arc<AppState>>,
+    Path(supervisor_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let reset_cnt = state.dispatcher.reset_supervisor(supervisor_id).await?;
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({ "message": "ok", "reset_count": reset_cnt })),
+    ))
+}
+
+pub async fn refresh_sources_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let cnt = state.dispatcher.refresh_source_ids().await?;
+    Ok((
+        StatusCode::OK,
+        Json(serde_json::json!({ "message": "ok", "source_ids_count": cnt })),
+    ))
+}
+
+const DRAIN_DEFAULT_TIMEOUT_SECS: u64 = 600;
+const DRAIN_STATUS_URL: &str = "/drain_and_exit/status";
+
+#[derive(Deserialize)]
+pub struct DrainAndExitParams {
+    timeout_secs: Option<u64>,
+    /// Respond only after the drain finished instead of `202` right away.
+    #[serde(default)]
+    wait: bool,
+}
+
+pub async fn drain_and_exit_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DrainAndExitParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ApiError> {
+    check_admin_api_key(state.admin_api_key.as_deref(), &headers)?;
+    let timeout = Duration::from_secs(params.timeout_secs.unwrap_or(DRAIN_DEFAULT_TIMEOUT_SECS));
+    let Some(drain) = state
+        .dispatcher
+        .clone()
+        .start_drain_and_exit(timeout, state.cancellation_token.clone())
+    else {
+        return Ok((
+            StatusCode::CONFLICT,
             Json(serde_json::json!({
-                "message": format!("process {} not found", id)
+                "message": "Drain already started",
+                "status_url": DRAIN_STATUS_URL,
             })),
-        ),
-        Err(ReportFinishError::Db(e)) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    };
+    if !params.wait {
+        return Ok((
+            StatusCode::ACCEPTED,
             Json(serde_json::json!({
-                "message": format!("Failed to report process finish: {}", e)
+                "message": "Draining",
+                "status_url": DRAIN_STATUS_URL,
             })),
-        ),
+        ));
+    }
+    //the drain runs in its own task, so a request timeout only stops the waiting
+    let status = drain
+        .await
+        .map_err(|e| ApiError::Internal(format!("Drain task failed: {}", e)))?;
+    Ok((StatusCode::OK, Json(serde_json::json!(status))))
+}
+
+pub async fn drain_status_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        Json(serde_json::json!(state.dispatcher.drain_status())),
+    )
+}
+
+/// Liveness of each DB pool for load balancer probes; unlike `/ready` it ignores
+/// the schedule and names the failing pool.
+pub async fn health_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let Ok(pools) = state.dispatcher.pool_health(&state.cancellation_token).await else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": "shutting_down" })),
+        );
+    };
+    if pools.values().all(|&ok| ok) {
+        return (StatusCode::OK, Json(serde_json::json!({ "status": "ok" })));
+    }
+    let mut body = serde_json::json!({ "status": "degraded" });
+    for (pool, ok) in pools {
+        body[pool] = serde_json::json!(ok);
+    }
+    (StatusCode::SERVICE_UNAVAILABLE, Json(body))
+}
+
+pub async fn info_handler(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let info = state.dispatcher.info().await?;
+    Ok((StatusCode::OK, Json(serde_json::json!(info))))
+}
+
+/// Response body of `GET /stats`: dispatcher counters plus claim admission.
+#[derive(Serialize)]
+struct StatsResponse {
+    #[serde(flatten)]
+    dispatcher: StatsSnapshot,
+    #[serde(flatten)]
+    claims: ClaimPermitsSnapshot,
+}
+
+pub async fn stats_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let stats = StatsResponse {
+        dispatcher: state.dispatcher.stats(),
+        claims: state.claim_permits.snapshot(),
+    };
+    (StatusCode::OK, Json(serde_json::json!(stats)))
+}
+
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let body = render_metrics(
+        &state.dispatcher.stats(),
+        &state.claim_permits.snapshot(),
+        state.dispatcher.source_locks_len(),
+        &state.dispatcher.latency_histograms(),
+    );
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+        body,
+    )
+}
+
+pub async fn ready_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let readiness = state.dispatcher.readiness().await;
+    if readiness.is_ready() {
+        return (
+            StatusCode::OK,
+            Json(serde_json::json!({ "status": "ready" })),
+        );
+    }
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({
+            "status": "not_ready",
+            "db": readiness.db,
+            "schedule": readiness.schedule,
+        })),
+    )
+}
+
+const LATENCY_STATS_DEFAULT_TOP: usize = 20;
+const LATENCY_STATS_MAX_TOP: usize = 500;
+
+#[derive(Deserialize)]
+pub struct LatencyStatsParams {
+    top: Option<usize>,
+}
+
+pub async fn latency_stats_handler(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<LatencyStatsParams>,
+) -> impl IntoResponse {
+    let top = params
+        .top
+        .unwrap_or(LATENCY_STATS_DEFAULT_TOP)
+        .min(LATENCY_STATS_MAX_TOP);
+    (
+        StatusCode::OK,
+        Json(serde_json::json!(state.dispatcher.busiest_source_latencies(top))),
+    )
+}
+
+const EXPORT_CSV_HEADER: [&str; 6] = [
+    "uuid",
+    "source_id",
+    "state",
+    "mode",
+    "created_at",
+    "supervisor_id",
+];
+
+pub async fn export_csv_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(e) = check_admin_api_key(state.admin_api_key.as_deref(), &headers) {
+        return e.into_response();
+    }
+    let processes = match state.dispatcher.export_non_finished_processes().await {
+        Ok(processes) => processes,
+        Err(e) => return ApiError::from(e).into_response(),
+    };
+
+    let header_line = stream::once(async { csv_line(EXPORT_CSV_HEADER) });
+    let lines = processes
+        .map_err(std::io::Error::other)
+        .and_then(|process| async move { csv_line(exported_process_record(&process)) });
+    let body = Body::from_stream(header_line.chain(lines));
+
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn exported_process_record(process: &ExportedProcess) -> [String; 6] {
+    [
+        process.uuid.to_string(),
+        process.source_id.to_string(),
+        process.state.clone(),
+        process.mode.to_string(),
+        process.created_at.clone().unwrap_or_default(),
+        process
+            .supervisor_id
+            .map(|id| id.to_string())
+            .unwrap_or_default(),
+    ]
+}
+
+fn csv_line<I, T>(record: I) -> Result<Bytes, std::io::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(record)?;
+    writer.into_inner().map(Bytes::from).map_err(|e| e.into_error())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_api_key_check() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            check_admin_api_key(None, &headers)
+                .unwrap_err()
+                .status_code(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            check_admin_api_key(Some("secret"), &headers)
+                .unwrap_err()
+                .status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        headers.insert(API_KEY_HEADER, "wrong".parse().unwrap());
+        assert_eq!(
+            check_admin_api_key(Some("secret"), &headers)
+                .unwrap_err()
+                .status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        headers.insert(API_KEY_HEADER, "secret".parse().unwrap());
+        assert!(check_admin_api_key(Some("secret"), &headers).is_ok());
+    }
+
+    #[test]
+    fn test_exported_process_csv_line() {
+        let process = ExportedProcess {
+            uuid: Uuid::nil(),
+            source_id: 42,
+            state: "created".to_string(),
+            mode: 1,
+            created_at: Some("2024-10-13 13:13:23.000".to_string()),
+            supervisor_id: None,
+        };
+
+        let line = csv_line(exported_process_record(&process)).unwrap();
+
+        assert_eq!(
+            line,
+            Bytes::from(
+                "00000000-0000-0000-0000-000000000000,42,created,1,2024-10-13 13:13:23.000,\n"
+            )
+        );
+        assert_eq!(
+            csv_line(EXPORT_CSV_HEADER).unwrap(),
+            Bytes::from("uuid,source_id,state,mode,created_at,supervisor_id\n")
+        );
     }
 }
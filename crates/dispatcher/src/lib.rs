@@ -1,7 +1,11 @@
-mod db_repository;
+pub mod db_repository;
 pub mod dispatcher;
 pub mod env;
 pub mod http_server;
+pub mod process_class;
+pub mod process_store;
+#[cfg(feature = "statsd")]
+pub mod statsd;
 
 pub mod async_keyed_mutex;
 pub mod cancellation_ext;
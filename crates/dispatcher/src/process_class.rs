@@ -0,0 +1,145 @@
+use crate::db_repository::ProcessReadsPool;
+use shared::{ProcessingMode, DEFAULT_PROCESS_CLASS};
+use std::str::FromStr;
+
+const ALL_PROCESSING_MODES: [ProcessingMode; 2] =
+    [ProcessingMode::Regular, ProcessingMode::Sandbox];
+
+/// A group of processing modes sharing an assignment priority and, optionally,
+/// the pool serving their candidate reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessClass {
+    pub name: String,
+    pub modes: Vec<ProcessingMode>,
+    /// Overrides `PROCESS_READS_POOL` for the candidates of this class.
+    pub reads_pool: Option<ProcessReadsPool>,
+}
+
+impl ProcessClass {
+    /// Numeric `mode` column values of the class, comma-separated for `FIND_IN_SET`.
+    pub fn mode_values(&self) -> String {
+        self.modes
+            .iter()
+            .map(|&mode| u8::from(mode).to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Process classes in assignment priority order, highest first (`PROCESS_CLASSES`).
+/// Every `ProcessingMode` belongs to exactly one class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessClasses(Vec<ProcessClass>);
+
+impl ProcessClasses {
+    pub fn iter(&self) -> impl Iterator<Item = &ProcessClass> {
+        self.0.iter()
+    }
+
+    pub fn class_of(&self, mode: ProcessingMode) -> &str {
+        self.0
+            .iter()
+            .find(|class| class.modes.contains(&mode))
+            .map_or(DEFAULT_PROCESS_CLASS, |class| class.name.as_str())
+    }
+}
+
+impl Default for ProcessClasses {
+    fn default() -> Self {
+        ProcessClasses(vec![ProcessClass {
+            name: DEFAULT_PROCESS_CLASS.to_string(),
+            modes: ALL_PROCESSING_MODES.to_vec(),
+            reads_pool: None,
+        }])
+    }
+}
+
+/// Parses `name:mode[|mode...][@pool]` entries separated by commas, e.g.
+/// `interactive:sandbox,batch:regular@pd_read`.
+impl FromStr for ProcessClasses {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut classes: Vec<ProcessClass> = Vec::new();
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (name, rest) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("'{}' is not name:modes", entry))?;
+            let (modes, reads_pool) = match rest.split_once('@') {
+                Some((modes, pool)) => (modes, Some(pool.parse::<ProcessReadsPool>()?)),
+                None => (rest, None),
+            };
+            let modes = modes
+                .split('|')
+                .map(|mode| parse_processing_mode(mode.trim()))
+                .collect::<Result<Vec<_>, _>>()?;
+            if classes.iter().any(|class| class.name == name) {
+                return Err(format!("class '{}' is defined twice", name));
+            }
+            classes.push(ProcessClass {
+                name: name.trim().to_string(),
+                modes,
+                reads_pool,
+            });
+        }
+
+        for mode in ALL_PROCESSING_MODES {
+            let cnt = classes
+                .iter()
+                .filter(|class| class.modes.contains(&mode))
+                .count();
+            if cnt != 1 {
+                return Err(format!("mode {} must belong to exactly one class", mode));
+            }
+        }
+        Ok(ProcessClasses(classes))
+    }
+}
+
+/// `regular` or `sandbox`, case-insensitive.
+pub fn parse_processing_mode(value: &str) -> Result<ProcessingMode, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "regular" => Ok(ProcessingMode::Regular),
+        "sandbox" => Ok(ProcessingMode::Sandbox),
+        other => Err(format!("unknown processing mode '{}'", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classes_keep_priority_order() {
+        let classes: ProcessClasses = "interactive:sandbox, batch:regular@pd_read"
+            .parse()
+            .unwrap();
+
+        let names: Vec<&str> = classes.iter().map(|class| class.name.as_str()).collect();
+        assert_eq!(names, vec!["interactive", "batch"]);
+        assert_eq!(classes.class_of(ProcessingMode::Regular), "batch");
+        assert_eq!(
+            classes.iter().last().unwrap().reads_pool,
+            Some(ProcessReadsPool::PdRead)
+        );
+        assert_eq!(
+            ProcessClasses::default().class_of(ProcessingMode::Sandbox),
+            DEFAULT_PROCESS_CLASS
+        );
+    }
+
+    #[test]
+    fn test_every_mode_needs_exactly_one_class() {
+        assert!("batch:regular".parse::<ProcessClasses>().is_err());
+        assert!("a:regular|sandbox,b:sandbox"
+            .parse::<ProcessClasses>()
+            .is_err());
+        assert!("a:regular,a:sandbox".parse::<ProcessClasses>().is_err());
+        assert!("a:regular,b:turbo".parse::<ProcessClasses>().is_err());
+        assert!("a:regular|sandbox@mvp".parse::<ProcessClasses>().is_err());
+    }
+}
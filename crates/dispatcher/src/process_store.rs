@@ -0,0 +1,237 @@
+#[cfg(test)]
+pub(crate) mod in_memory;
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use shared::{DispatchState, ParseError, ProcessingMode};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Upper bound of bound parameters in a single `IN (...)` list.
+pub const SOURCE_IDS_CHUNK_SIZE: usize = 1000;
+
+/// Stream of records fetched lazily from the store.
+pub type RecordStream<T> = BoxStream<'static, Result<T, sqlx::Error>>;
+
+/// A `dispatcher_processes` row. Timestamps are UTC in the MySQL `TIMESTAMP(3)`
+/// format (`%Y-%m-%d %H:%M:%S%.3f`), `mode` is the numeric `ProcessingMode`
+/// (`state` / `mode` may still hold values this build does not know).
+/// `created_at` is `None` for a NULL column (unknown age).
+#[derive(Debug, Clone)]
+pub struct ProcessRecord {
+    pub uuid: Uuid,
+    pub source_id: u32,
+    pub supervisor_id: Option<Uuid>,
+    pub state: String,
+    pub mode: u8,
+    pub failure_reason: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: String,
+}
+
+impl ProcessRecord {
+    pub fn dispatch_state(&self) -> Result<DispatchState, ParseError> {
+        DispatchState::try_from(self.state.as_str())
+    }
+
+    pub fn processing_mode(&self) -> Result<ProcessingMode, ParseError> {
+        ProcessingMode::try_from(self.mode)
+    }
+}
+
+/// Filter and page of `list_processes`; `None` fields match every process.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessFilter {
+    pub source_id: Option<u32>,
+    pub state: Option<DispatchState>,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+/// Assignable process of `get_available_processes_sources_stream`.
+#[derive(Debug)]
+pub struct CandidateRow {
+    pub uuid: Uuid,
+    pub source_id: u32,
+    pub state: String,
+    pub mode: u8,
+    pub created_at: Option<String>,
+}
+
+impl CandidateRow {
+    pub fn dispatch_state(&self) -> Result<DispatchState, ParseError> {
+        DispatchState::try_from(self.state.as_str())
+    }
+
+    pub fn processing_mode(&self) -> Result<ProcessingMode, ParseError> {
+        ProcessingMode::try_from(self.mode)
+    }
+}
+
+/// Storage operations `Dispatcher` relies on. `DbRepository` is the MySQL
+/// implementation; other backends only have to keep the semantics documented
+/// here (errors are reported as `sqlx::Error`, e.g. `sqlx::Error::Protocol`).
+#[async_trait]
+pub trait ProcessStore: Send + Sync {
+    /// Cheap liveness check of every underlying connection.
+    async fn ping(&self) -> Result<(), sqlx::Error>;
+
+    /// `ping` of each pool on its own, keyed by pool name (`pd`, `mvp`, `pd_read`),
+    /// so a health check can tell which one is down.
+    async fn ping_each(&self) -> Vec<(&'static str, Result<(), sqlx::Error>)> {
+        vec![("pd", self.ping().await)]
+    }
+
+    /// Server version of the primary DB (`SELECT VERSION()`).
+    async fn db_version(&self) -> Result<String, sqlx::Error>;
+
+    /// Latest successfully applied `db/migrations/` version, `None` if the
+    /// schema was never migrated through sqlx.
+    async fn applied_migration_version(&self) -> Result<Option<i64>, sqlx::Error>;
+
+    /// Ids of the sources that should be scheduled.
+    async fn available_source_ids_stream(&self) -> Result<RecordStream<u32>, sqlx::Error>;
+
+    async fn insert_new_process(
+        &self,
+        source_id: u32,
+        state: DispatchState,
+        processing_mode: ProcessingMode,
+    ) -> Result<Uuid, sqlx::Error>;
+
+    /// Latest non-sandbox process of a source; sandbox processes are created on
+    /// demand and never count for the scheduler's guards.
+    async fn get_latest_process_for(
+        &self,
+        source_id: u32,
+    ) -> Result<Option<ProcessRecord>, sqlx::Error>;
+
+    /// Whether the source has a process of `processing_mode` that is not finished
+    /// (`Completed`, `Failed`, or `Error` with `ERROR_IS_TERMINAL`).
+    async fn has_unfinished_process(
+        &self,
+        source_id: u32,
+        processing_mode: ProcessingMode,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Returns the given source ids (in their order) whose latest non-sandbox process
+    /// is missing, or finished and created before `reprocess_cutoff` (UTC,
+    /// `%Y-%m-%d %H:%M:%S%.3f`).
+    async fn sources_needing_new_process(
+        &self,
+        active_source_ids: &[u32],
+        reprocess_cutoff: &str,
+    ) -> Result<Vec<u32>, sqlx::Error>;
+
+    /// Moves the `Created` processes of the given sources (at most
+    /// `SOURCE_IDS_CHUNK_SIZE`) to `Pending` in one statement.
+    /// Returns the number of promoted processes.
+    async fn promote_created_to_pending(&self, source_ids: &[u32]) -> Result<u64, sqlx::Error>;
+
+    /// `Created` / `Pending` processes of a source, oldest first.
+    async fn get_available_source_processes_stream(
+        &self,
+        source_id: u32,
+        limit: u32,
+    ) -> Result<RecordStream<ProcessRecord>, sqlx::Error>;
+
+    /// The oldest assignable processes, one record per candidate, so the
+    /// caller can claim a candidate without another lookup per source.
+    async fn get_available_processes_sources_stream(
+        &self,
+        supervisor_id: Uuid,
+        limit: u32,
+    ) -> Result<RecordStream<CandidateRow>, sqlx::Error>;
+
+    /// Not `Completed` / `Failed` processes, oldest first.
+    async fn non_finished_processes_stream(
+        &self,
+        limit: u32,
+    ) -> Result<RecordStream<ProcessRecord>, sqlx::Error>;
+
+    /// Assigns a process to the supervisor only if it is still `Created` / `Pending`
+    /// and no supervisor holds it yet. Returns the number of affected rows (`0` =
+    /// another supervisor won the race or the process expired meanwhile).
+    async fn try_claim_process(
+        &self,
+        id: Uuid,
+        supervisor_id: Uuid,
+        new_state: DispatchState,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// Claims a specific process for the supervisor only if it is still unassigned
+    /// and assignable. Returns the number of affected rows (`0` = not claimable).
+    async fn claim_process_by_id(
+        &self,
+        id: Uuid,
+        supervisor_id: Uuid,
+        assigned_state: DispatchState,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// Claims a candidate from `get_available_processes_sources_stream` if it still
+    /// matches the candidate condition. Returns the number of affected rows
+    /// (`0` = taken or finished in the meantime).
+    async fn claim_candidate(
+        &self,
+        id: Uuid,
+        supervisor_id: Uuid,
+        assigned_state: DispatchState,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// Number of unassigned `Created` / `Pending` processes created before
+    /// `created_at`, of `source_id` only if given.
+    async fn count_queued_before(
+        &self,
+        source_id: Option<u32>,
+        created_at: &str,
+    ) -> Result<u64, sqlx::Error>;
+
+    /// Number of unassigned `Created` / `Pending` processes.
+    async fn count_queued_processes(&self) -> Result<u64, sqlx::Error>;
+
+    /// Number of `Processing` processes owned by a supervisor.
+    async fn count_in_flight_processes(&self) -> Result<u64, sqlx::Error>;
+
+    async fn get_process(&self, id: Uuid) -> Result<Option<ProcessRecord>, sqlx::Error>;
+
+    /// Processes matching the filter, newest first.
+    async fn list_processes(
+        &self,
+        filter: &ProcessFilter,
+    ) -> Result<Vec<ProcessRecord>, sqlx::Error>;
+
+    /// Reads a process for a status response, possibly from a lagging replica
+    /// unless `from_primary` is set.
+    async fn get_process_for_status(
+        &self,
+        id: Uuid,
+        from_primary: bool,
+    ) -> Result<Option<ProcessRecord>, sqlx::Error>;
+
+    /// Sets the state of a `Processing` process assigned to a supervisor and
+    /// returns the updated row, `None` for an unknown id or any other process
+    /// (e.g. one already finished by an earlier report).
+    async fn update_process_state(
+        &self,
+        id: Uuid,
+        state: DispatchState,
+    ) -> Result<Option<ProcessRecord>, sqlx::Error>;
+
+    /// Returns a not finished process held by a supervisor (or `Processing` /
+    /// `Error`) to the queue as `Created` and unassigned. `Error` counts as
+    /// finished with `ERROR_IS_TERMINAL`. Returns the number of released processes
+    /// (`0` = unknown id, finished process or a queued process nobody holds).
+    async fn release_process(&self, id: Uuid) -> Result<u64, sqlx::Error>;
+
+    /// Returns the supervisor's `Processing` processes to the queue (`Created`,
+    /// unassigned, like a release). Returns the number of reset processes.
+    async fn reset_supervisor_processes(&self, supervisor_id: Uuid) -> Result<u64, sqlx::Error>;
+
+    /// Requeues `Processing` processes not updated for longer than `older_than`
+    /// as `Created` and unassigned. Returns the number of requeued processes.
+    async fn reset_stale_processes(&self, older_than: Duration) -> Result<u64, sqlx::Error>;
+
+    /// Fails not yet running processes older than `MAX_PROCESS_AGE_SECS`.
+    /// Returns the number of expired processes (always `0` when no max age is configured).
+    async fn expire_old_processes(&self, failure_reason: &str) -> Result<u64, sqlx::Error>;
+}
@@ -0,0 +1,508 @@
+use super::{CandidateRow, ProcessFilter, ProcessRecord, ProcessStore, RecordStream};
+use crate::process_class::ProcessClasses;
+use async_trait::async_trait;
+use chrono::{TimeDelta, Utc};
+use futures::StreamExt;
+use shared::{DispatchState, ProcessingMode};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// `ProcessStore` kept in memory, for tests that do not need MySQL.
+/// Processes never expire (no `MAX_PROCESS_AGE_SECS`) and `Error` is retryable,
+/// except for `has_unfinished_process` / `release_process` of a store built
+/// `with_terminal_error`.
+#[derive(Default)]
+pub(crate) struct InMemoryProcessStore {
+    source_ids: Vec<u32>,
+    processes: Mutex<Vec<ProcessRecord>>,
+    failing_source_ids_lookups: AtomicU32,
+    candidate_lookups_before_failure: Mutex<Option<u32>>,
+    process_classes: ProcessClasses,
+    error_is_terminal: bool,
+}
+
+impl InMemoryProcessStore {
+    pub fn new(source_ids: Vec<u32>) -> InMemoryProcessStore {
+        InMemoryProcessStore {
+            source_ids,
+            processes: Mutex::default(),
+            failing_source_ids_lookups: AtomicU32::new(0),
+            candidate_lookups_before_failure: Mutex::new(None),
+            process_classes: ProcessClasses::default(),
+            error_is_terminal: false,
+        }
+    }
+
+    /// Treats `Error` as finished, like `ERROR_IS_TERMINAL=true`.
+    pub fn with_terminal_error(mut self) -> InMemoryProcessStore {
+        self.error_is_terminal = true;
+        self
+    }
+
+    fn finished_states(&self) -> &'static [DispatchState] {
+        if self.error_is_terminal {
+            &[DispatchState::Completed, DispatchState::Failed, DispatchState::Error]
+        } else {
+            &[DispatchState::Completed, DispatchState::Failed]
+        }
+    }
+
+    pub fn with_process_classes(mut self, process_classes: ProcessClasses) -> InMemoryProcessStore {
+        self.process_classes = process_classes;
+        self
+    }
+
+    /// Sets `created_at` of a process to NULL, like a row without a column default.
+    pub fn clear_created_at(&self, id: Uuid) {
+        for process in self.processes.lock().unwrap().iter_mut() {
+            if process.uuid == id {
+                process.created_at = None;
+            }
+        }
+    }
+
+    /// Overwrites `updated_at`, e.g. to make a process look stale.
+    pub fn set_updated_at(&self, id: Uuid, updated_at: &str) {
+        for process in self.processes.lock().unwrap().iter_mut() {
+            if process.uuid == id {
+                process.updated_at = updated_at.to_string();
+            }
+        }
+    }
+
+    /// Overwrites the raw `state` column, e.g. with a value this build does not know.
+    pub fn set_raw_state(&self, id: Uuid, state: &str) {
+        for process in self.processes.lock().unwrap().iter_mut() {
+            if process.uuid == id {
+                process.state = state.to_string();
+            }
+        }
+    }
+
+    /// Makes the next `cnt` source id lookups fail like an unreachable DB.
+    pub fn fail_source_ids_lookups(&self, cnt: u32) {
+        self.failing_source_ids_lookups.store(cnt, Ordering::SeqCst);
+    }
+
+    /// Makes every candidate lookup after the next `cnt` fail like an unreachable DB.
+    pub fn fail_candidate_lookups_after(&self, cnt: u32) {
+        *self.candidate_lookups_before_failure.lock().unwrap() = Some(cnt);
+    }
+
+    /// Processes matching `filter`, oldest first (insertion order on equal `created_at`).
+    fn select(&self, filter: impl Fn(&ProcessRecord) -> bool) -> Vec<ProcessRecord> {
+        let mut processes: Vec<ProcessRecord> = self
+            .processes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|process| filter(process))
+            .cloned()
+            .collect();
+        processes.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        processes
+    }
+
+    /// Applies `update` to the processes matching `filter`, returns their number.
+    fn update(
+        &self,
+        filter: impl Fn(&ProcessRecord) -> bool,
+        update: impl Fn(&mut ProcessRecord),
+    ) -> u64 {
+        let mut cnt = 0;
+        for process in self.processes.lock().unwrap().iter_mut() {
+            if filter(process) {
+                update(process);
+                process.updated_at = now_db();
+                cnt += 1;
+            }
+        }
+        cnt
+    }
+}
+
+const DB_DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+fn now_db() -> String {
+    Utc::now().format(DB_DATETIME_FORMAT).to_string()
+}
+
+fn has_state(process: &ProcessRecord, states: &[DispatchState]) -> bool {
+    //an unknown state matches nothing, like the `state IN (...)` of the SQL
+    process
+        .dispatch_state()
+        .is_ok_and(|state| states.contains(&state))
+}
+
+fn is_candidate_for(process: &ProcessRecord, supervisor_id: Uuid) -> bool {
+    (has_state(process, &[DispatchState::Created, DispatchState::Pending])
+        && process.supervisor_id.is_none())
+        || (has_state(process, &[DispatchState::Error])
+            && process.supervisor_id == Some(supervisor_id))
+}
+
+fn mode_of(process: &ProcessRecord) -> ProcessingMode {
+    ProcessingMode::try_from(process.mode).expect("Unexpected 'mode' value")
+}
+
+fn stream_of<T: Send + 'static>(items: Vec<T>) -> RecordStream<T> {
+    futures::stream::iter(items.into_iter().map(Ok)).boxed()
+}
+
+#[async_trait]
+impl ProcessStore for InMemoryProcessStore {
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    async fn db_version(&self) -> Result<String, sqlx::Error> {
+        Ok("in-memory".to_string())
+    }
+
+    async fn applied_migration_version(&self) -> Result<Option<i64>, sqlx::Error> {
+        Ok(None)
+    }
+
+    async fn available_source_ids_stream(&self) -> Result<RecordStream<u32>, sqlx::Error> {
+        if self
+            .failing_source_ids_lookups
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |cnt| cnt.checked_sub(1))
+            .is_ok()
+        {
+            return Err(sqlx::Error::PoolTimedOut);
+        }
+        Ok(stream_of(self.source_ids.clone()))
+    }
+
+    async fn insert_new_process(
+        &self,
+        source_id: u32,
+        state: DispatchState,
+        processing_mode: ProcessingMode,
+    ) -> Result<Uuid, sqlx::Error> {
+        let uuid = Uuid::new_v4();
+        self.processes.lock().unwrap().push(ProcessRecord {
+            uuid,
+            source_id,
+            supervisor_id: None,
+            state: state.to_string(),
+            mode: u8::from(processing_mode),
+            failure_reason: None,
+            created_at: Some(now_db()),
+            updated_at: now_db(),
+        });
+        Ok(uuid)
+    }
+
+    async fn get_latest_process_for(
+        &self,
+        source_id: u32,
+    ) -> Result<Option<ProcessRecord>, sqlx::Error> {
+        let latest = self
+            .select(|process| {
+                process.source_id == source_id && mode_of(process) != ProcessingMode::Sandbox
+            })
+            .pop();
+        //a real query yields before its result is used, which is where check-then-insert races
+        tokio::task::yield_now().await;
+        Ok(latest)
+    }
+
+    async fn has_unfinished_process(
+        &self,
+        source_id: u32,
+        processing_mode: ProcessingMode,
+    ) -> Result<bool, sqlx::Error> {
+        Ok(!self
+            .select(|process| {
+                process.source_id == source_id
+                    && mode_of(process) == processing_mode
+                    && !has_state(process, self.finished_states())
+            })
+            .is_empty())
+    }
+
+    async fn sources_needing_new_process(
+        &self,
+        active_source_ids: &[u32],
+        reprocess_cutoff: &str,
+    ) -> Result<Vec<u32>, sqlx::Error> {
+        let mut source_ids = Vec::new();
+        for &source_id in active_source_ids {
+            let needs_new_process = match self.get_latest_process_for(source_id).await? {
+                Some(process) => {
+                    has_state(&process, &[DispatchState::Completed, DispatchState::Failed])
+                        && process
+                            .created_at
+                            .as_deref()
+                            .is_none_or(|created_at| created_at < reprocess_cutoff)
+                }
+                None => true,
+            };
+            if needs_new_process {
+                source_ids.push(source_id);
+            }
+        }
+        Ok(source_ids)
+    }
+
+    async fn promote_created_to_pending(&self, source_ids: &[u32]) -> Result<u64, sqlx::Error> {
+        Ok(self.update(
+            |process| {
+                source_ids.contains(&process.source_id)
+                    && has_state(process, &[DispatchState::Created])
+            },
+            |process| process.state = DispatchState::Pending.to_string(),
+        ))
+    }
+
+    async fn get_available_source_processes_stream(
+        &self,
+        source_id: u32,
+        limit: u32,
+    ) -> Result<RecordStream<ProcessRecord>, sqlx::Error> {
+        let mut processes = self.select(|process| {
+            process.source_id == source_id
+                && has_state(process, &[DispatchState::Created, DispatchState::Pending])
+        });
+        processes.truncate(limit as usize);
+        Ok(stream_of(processes))
+    }
+
+    async fn get_available_processes_sources_stream(
+        &self,
+        supervisor_id: Uuid,
+        limit: u32,
+    ) -> Result<RecordStream<CandidateRow>, sqlx::Error> {
+        if let Some(remaining) = self.candidate_lookups_before_failure.lock().unwrap().as_mut() {
+            if *remaining == 0 {
+                return Err(sqlx::Error::PoolTimedOut);
+            }
+            *remaining -= 1;
+        }
+        let candidates = self
+            .process_classes
+            .iter()
+            .flat_map(|class| {
+                self.select(|process| {
+                    is_candidate_for(process, supervisor_id)
+                        && class.modes.contains(&mode_of(process))
+                })
+            })
+            .take(limit as usize)
+            .map(|process| CandidateRow {
+                uuid: process.uuid,
+                source_id: process.source_id,
+                state: process.state,
+                mode: process.mode,
+                created_at: process.created_at,
+            })
+            .collect();
+        Ok(stream_of(candidates))
+    }
+
+    async fn non_finished_processes_stream(
+        &self,
+        limit: u32,
+    ) -> Result<RecordStream<ProcessRecord>, sqlx::Error> {
+        let mut processes = self.select(|process| {
+            !has_state(process, &[DispatchState::Completed, DispatchState::Failed])
+        });
+        processes.truncate(limit as usize);
+        Ok(stream_of(processes))
+    }
+
+    async fn try_claim_process(
+        &self,
+        id: Uuid,
+        supervisor_id: Uuid,
+        new_state: DispatchState,
+    ) -> Result<u64, sqlx::Error> {
+        Ok(self.update(
+            |process| {
+                process.uuid == id
+                    && process.supervisor_id.is_none()
+                    && has_state(process, &[DispatchState::Created, DispatchState::Pending])
+            },
+            |process| {
+                process.supervisor_id = Some(supervisor_id);
+                process.state = new_state.to_string();
+            },
+        ))
+    }
+
+    async fn claim_process_by_id(
+        &self,
+        id: Uuid,
+        supervisor_id: Uuid,
+        assigned_state: DispatchState,
+    ) -> Result<u64, sqlx::Error> {
+        Ok(self.update(
+            |process| {
+                process.uuid == id
+                    && process.supervisor_id.is_none()
+                    && has_state(
+                        process,
+                        &[
+                            DispatchState::Created,
+                            DispatchState::Pending,
+                            DispatchState::Error,
+                        ],
+                    )
+            },
+            |process| {
+                process.supervisor_id = Some(supervisor_id);
+                process.state = assigned_state.to_string();
+            },
+        ))
+    }
+
+    async fn claim_candidate(
+        &self,
+        id: Uuid,
+        supervisor_id: Uuid,
+        assigned_state: DispatchState,
+    ) -> Result<u64, sqlx::Error> {
+        Ok(self.update(
+            |process| process.uuid == id && is_candidate_for(process, supervisor_id),
+            |process| {
+                process.supervisor_id = Some(supervisor_id);
+                process.state = assigned_state.to_string();
+            },
+        ))
+    }
+
+    async fn count_queued_before(
+        &self,
+        source_id: Option<u32>,
+        created_at: &str,
+    ) -> Result<u64, sqlx::Error> {
+        Ok(self
+            .select(|process| {
+                has_state(process, &[DispatchState::Created, DispatchState::Pending])
+                    && process.supervisor_id.is_none()
+                    && source_id.is_none_or(|source_id| process.source_id == source_id)
+                    && process
+                        .created_at
+                        .as_deref()
+                        .is_none_or(|process_created_at| process_created_at < created_at)
+            })
+            .len() as u64)
+    }
+
+    async fn count_queued_processes(&self) -> Result<u64, sqlx::Error> {
+        Ok(self
+            .select(|process| {
+                has_state(process, &[DispatchState::Created, DispatchState::Pending])
+                    && process.supervisor_id.is_none()
+            })
+            .len() as u64)
+    }
+
+    async fn count_in_flight_processes(&self) -> Result<u64, sqlx::Error> {
+        Ok(self
+            .select(|process| {
+                has_state(process, &[DispatchState::Processing]) && process.supervisor_id.is_some()
+            })
+            .len() as u64)
+    }
+
+    async fn get_process(&self, id: Uuid) -> Result<Option<ProcessRecord>, sqlx::Error> {
+        Ok(self.select(|process| process.uuid == id).pop())
+    }
+
+    async fn list_processes(
+        &self,
+        filter: &ProcessFilter,
+    ) -> Result<Vec<ProcessRecord>, sqlx::Error> {
+        let mut processes = self.select(|process| {
+            filter.source_id.is_none_or(|source_id| process.source_id == source_id)
+                && filter
+                    .state
+                    .as_ref()
+                    .is_none_or(|state| process.state == state.to_string())
+        });
+        processes.reverse();
+        Ok(processes
+            .into_iter()
+            .skip(filter.offset as usize)
+            .take(filter.limit as usize)
+            .collect())
+    }
+
+    async fn get_process_for_status(
+        &self,
+        id: Uuid,
+        _from_primary: bool,
+    ) -> Result<Option<ProcessRecord>, sqlx::Error> {
+        self.get_process(id).await
+    }
+
+    async fn update_process_state(
+        &self,
+        id: Uuid,
+        state: DispatchState,
+    ) -> Result<Option<ProcessRecord>, sqlx::Error> {
+        let updated = self.update(
+            |process| {
+                process.uuid == id
+                    && has_state(process, &[DispatchState::Processing])
+                    && process.supervisor_id.is_some()
+            },
+            |process| process.state = state.to_string(),
+        );
+        if updated == 0 {
+            return Ok(None);
+        }
+        Ok(self.select(|process| process.uuid == id).pop())
+    }
+
+    async fn release_process(&self, id: Uuid) -> Result<u64, sqlx::Error> {
+        Ok(self.update(
+            |process| {
+                process.uuid == id
+                    && !has_state(process, self.finished_states())
+                    && (process.supervisor_id.is_some()
+                        || has_state(process, &[DispatchState::Processing, DispatchState::Error]))
+            },
+            |process| {
+                process.supervisor_id = None;
+                process.state = DispatchState::Created.to_string();
+            },
+        ))
+    }
+
+    async fn reset_supervisor_processes(&self, supervisor_id: Uuid) -> Result<u64, sqlx::Error> {
+        Ok(self.update(
+            |process| {
+                process.supervisor_id == Some(supervisor_id)
+                    && has_state(process, &[DispatchState::Processing])
+            },
+            |process| {
+                process.supervisor_id = None;
+                process.state = DispatchState::Created.to_string();
+            },
+        ))
+    }
+
+    async fn reset_stale_processes(&self, older_than: Duration) -> Result<u64, sqlx::Error> {
+        let cutoff = (Utc::now() - TimeDelta::from_std(older_than).unwrap())
+            .format(DB_DATETIME_FORMAT)
+            .to_string();
+        Ok(self.update(
+            |process| {
+                has_state(process, &[DispatchState::Processing]) && process.updated_at < cutoff
+            },
+            |process| {
+                process.supervisor_id = None;
+                process.state = DispatchState::Created.to_string();
+            },
+        ))
+    }
+
+    async fn expire_old_processes(&self, _failure_reason: &str) -> Result<u64, sqlx::Error> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,202 @@
+use crate::cancellation_ext::sleep_unless_cancelled;
+use crate::dispatcher::{Dispatcher, HookError, PostCommitHook, ProcessEvent, ScheduleReport};
+use cadence::{Counted, Gauged, MetricError, QueuingMetricSink, Timed, UdpMetricSink};
+use std::net::UdpSocket;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, trace};
+
+const METRIC_PREFIX: &str = "process_dispatcher";
+
+/// Metrics queued for the sender thread; beyond that new metrics are dropped.
+const SINK_CAPACITY: usize = 4096;
+
+/// Interval of the queue depth and in-flight samples.
+const QUEUE_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// StatsD client on a cadence `QueuingMetricSink`: metrics are queued and sent
+/// over a non-blocking UDP socket by a background thread. A metric that cannot
+/// be queued or sent is dropped, so metrics never slow down or fail a request.
+pub struct StatsdClient {
+    client: cadence::StatsdClient,
+}
+
+impl StatsdClient {
+    /// `addr` is the `host:port` of the StatsD / DogStatsD agent (`STATSD_ADDR`).
+    pub fn connect(addr: &str) -> Result<StatsdClient, MetricError> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.set_nonblocking(true)?;
+        let sink =
+            QueuingMetricSink::with_capacity(UdpMetricSink::from(addr, socket)?, SINK_CAPACITY);
+        Ok(StatsdClient {
+            client: cadence::StatsdClient::from_sink(METRIC_PREFIX, sink),
+        })
+    }
+
+    pub fn count(&self, metric: &str, value: u64) {
+        if let Err(e) = self.client.count(metric, value) {
+            trace!("StatsD counter {} dropped: {}", metric, e);
+        }
+    }
+
+    pub fn gauge(&self, metric: &str, value: u64) {
+        if let Err(e) = self.client.gauge(metric, value) {
+            trace!("StatsD gauge {} dropped: {}", metric, e);
+        }
+    }
+
+    pub fn timing(&self, metric: &str, duration: Duration) {
+        if let Err(e) = self.client.time(metric, duration) {
+            trace!("StatsD timing {} dropped: {}", metric, e);
+        }
+    }
+
+    /// Counters and gauges of a finished schedule cycle.
+    pub fn schedule_cycle(&self, report: &ScheduleReport, duration: Duration) {
+        self.timing("schedule.cycle", duration);
+        self.count(
+            "schedule.processes_created",
+            report.processes_created.into(),
+        );
+        self.gauge("schedule.sources_scanned", report.sources_scanned as u64);
+        self.gauge("schedule.sources_deferred", report.sources_deferred as u64);
+        self.count("schedule.sources_failed", report.sources_failed as u64);
+    }
+
+    /// Queue depth and in-flight gauges, each with the latency of its count query.
+    pub async fn sample_queue(&self, dispatcher: &Dispatcher) {
+        let started_at = Instant::now();
+        match dispatcher.queue_depth().await {
+            Ok(depth) => {
+                self.gauge("queue.depth", depth);
+                self.timing("db.count_queued", started_at.elapsed());
+            }
+            Err(e) => error!("Error counting queued processes: {}", e),
+        }
+        let started_at = Instant::now();
+        match dispatcher.in_flight_count().await {
+            Ok(in_flight) => {
+                self.gauge("queue.in_flight", in_flight);
+                self.timing("db.count_in_flight", started_at.elapsed());
+            }
+            Err(e) => error!("Error counting in-flight processes: {}", e),
+        }
+    }
+
+    /// Every `QUEUE_SAMPLE_INTERVAL`, sends `sample_queue` until cancelled.
+    pub fn start_queue_sampling(
+        self: Arc<Self>,
+        dispatcher: Arc<Dispatcher>,
+        cancellation_token: CancellationToken,
+    ) {
+        tokio::task::spawn(async move {
+            loop {
+                self.sample_queue(&dispatcher).await;
+                if !sleep_unless_cancelled(&cancellation_token, QUEUE_SAMPLE_INTERVAL).await {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+impl PostCommitHook for StatsdClient {
+    fn name(&self) -> &str {
+        "statsd"
+    }
+
+    fn on_commit(&self, event: &ProcessEvent) -> Result<(), HookError> {
+        match event {
+            ProcessEvent::Created { .. } => self.count("processes.created", 1),
+            ProcessEvent::Assigned { .. } => self.count("processes.assigned", 1),
+            ProcessEvent::Finished { state, .. } => self.count(
+                &format!(
+                    "processes.finished.{}",
+                    state.to_string().to_ascii_lowercase()
+                ),
+                1,
+            ),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env::EnvParams;
+    use crate::process_store::in_memory::InMemoryProcessStore;
+    use crate::process_store::ProcessStore;
+    use shared::{DispatchState, ProcessingMode};
+    use uuid::Uuid;
+
+    fn receiver() -> (UdpSocket, StatsdClient) {
+        let receiver = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let client = StatsdClient::connect(&receiver.local_addr().unwrap().to_string()).unwrap();
+        (receiver, client)
+    }
+
+    fn recv_packet(receiver: &UdpSocket) -> String {
+        let mut buf = [0; 512];
+        let len = receiver.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    }
+
+    #[test]
+    fn test_events_are_sent_as_counters() {
+        let (receiver, client) = receiver();
+
+        client
+            .on_commit(&ProcessEvent::Assigned {
+                process_id: Uuid::new_v4(),
+                source_id: 1,
+                supervisor_id: Uuid::new_v4(),
+            })
+            .unwrap();
+        client.timing("schedule.cycle", Duration::from_millis(42));
+
+        assert_eq!(
+            recv_packet(&receiver),
+            "process_dispatcher.processes.assigned:1|c"
+        );
+        assert_eq!(
+            recv_packet(&receiver),
+            "process_dispatcher.schedule.cycle:42|ms"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_sample_sends_depth_and_query_latency() {
+        let (receiver, client) = receiver();
+        let store = Arc::new(InMemoryProcessStore::new(vec![]));
+        store
+            .insert_new_process(1, DispatchState::Created, ProcessingMode::Regular)
+            .await
+            .unwrap();
+        let dispatcher = Dispatcher::new(&EnvParams::for_tests(), store);
+
+        client.sample_queue(&dispatcher).await;
+
+        assert_eq!(recv_packet(&receiver), "process_dispatcher.queue.depth:1|g");
+        assert!(recv_packet(&receiver).starts_with("process_dispatcher.db.count_queued:"));
+        assert_eq!(
+            recv_packet(&receiver),
+            "process_dispatcher.queue.in_flight:0|g"
+        );
+        assert!(recv_packet(&receiver).starts_with("process_dispatcher.db.count_in_flight:"));
+    }
+
+    #[test]
+    fn test_unreachable_agent_does_not_fail() {
+        let (receiver, client) = receiver();
+        drop(receiver);
+
+        for _ in 0..10 {
+            client.count("processes.created", 1);
+        }
+    }
+}
@@ -5,6 +5,25 @@ use std::fmt::Display;
 pub const REPORT_STATUS_SUCCESS: &str = "success";
 pub const REPORT_STATUS_ERROR: &str = "error";
 
+/// Response header of a `204` from `GET /obtain_new_process/{supervisor_id}`
+/// carrying why no process was assigned, one of the `ASSIGN_REASON_*` values.
+pub const ASSIGN_REASON_HEADER: &str = "x-assign-reason";
+/// `ASSIGN_REASON_HEADER`: no assignable process exists.
+pub const ASSIGN_REASON_NO_SOURCES: &str = "no_sources";
+/// `ASSIGN_REASON_HEADER`: every candidate was claimed by another supervisor meanwhile.
+pub const ASSIGN_REASON_ALL_TAKEN: &str = "all_taken";
+/// `ASSIGN_REASON_HEADER`: `ASSIGN_MAX_PROBES` candidates were tried without a claim.
+pub const ASSIGN_REASON_PROBE_CAP_REACHED: &str = "probe_cap_reached";
+/// `ASSIGN_REASON_HEADER`: the dispatcher is draining and assigns nothing.
+pub const ASSIGN_REASON_DRAINING: &str = "draining";
+/// `ASSIGN_REASON_HEADER` of a `POST /assign_processes` batch cut short by a
+/// failed claim; the processes claimed before it are returned.
+pub const ASSIGN_REASON_ERROR: &str = "error";
+
+/// Process class of every mode when `PROCESS_CLASSES` is not set, and of a
+/// `ProcessStatus` from a dispatcher that does not send `class`.
+pub const DEFAULT_PROCESS_CLASS: &str = "default";
+
 const DISPATCH_STATE_CREATED: &str = "created";
 const DISPATCH_STATE_PENDING: &str = "pending";
 const DISPATCH_STATE_PROCESSING: &str = "processing";
@@ -22,18 +41,52 @@ pub enum DispatchState {
     Failed,
 }
 
-impl DispatchState {
-    pub fn new(value: &str) -> DispatchState {
+/// A `state` / `mode` value this build does not know, e.g. written by a newer
+/// service or a half-applied migration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    kind: &'static str,
+    value: String,
+}
+
+impl ParseError {
+    pub fn new(kind: &'static str, value: impl Into<String>) -> Self {
+        ParseError {
+            kind,
+            value: value.into(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unexpected {} value '{}'", self.kind, self.value)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl TryFrom<&str> for DispatchState {
+    type Error = ParseError;
+
+    fn try_from(value: &str) -> Result<Self, ParseError> {
         match value {
-            DISPATCH_STATE_CREATED => DispatchState::Created,
-            DISPATCH_STATE_PENDING => DispatchState::Pending,
-            DISPATCH_STATE_PROCESSING => DispatchState::Processing,
-            DISPATCH_STATE_ERROR => DispatchState::Error,
-            DISPATCH_STATE_COMPLETED => DispatchState::Completed,
-            DISPATCH_STATE_FAILED => DispatchState::Failed,
-            _ => panic!("Unexpected DispatchState value"),
+            DISPATCH_STATE_CREATED => Ok(DispatchState::Created),
+            DISPATCH_STATE_PENDING => Ok(DispatchState::Pending),
+            DISPATCH_STATE_PROCESSING => Ok(DispatchState::Processing),
+            DISPATCH_STATE_ERROR => Ok(DispatchState::Error),
+            DISPATCH_STATE_COMPLETED => Ok(DispatchState::Completed),
+            DISPATCH_STATE_FAILED => Ok(DispatchState::Failed),
+            other => Err(ParseError::new("DispatchState", other)),
         }
     }
+}
+
+impl DispatchState {
+    /// Panics on an unknown value; use `DispatchState::try_from` for DB values.
+    pub fn new(value: &str) -> DispatchState {
+        DispatchState::try_from(value).unwrap_or_else(|e| panic!("{}", e))
+    }
 
     pub fn is_finished(&self) -> bool {
         matches!(self, DispatchState::Completed | DispatchState::Failed)
@@ -56,19 +109,31 @@ impl Display for DispatchState {
 const PROCESSING_MODE_REGULAR: isize = 1;
 const PROCESSING_MODE_SANDBOX: isize = 2;
 
-#[derive(PartialEq, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum ProcessingMode {
     Regular = PROCESSING_MODE_REGULAR,
     Sandbox = PROCESSING_MODE_SANDBOX,
 }
 
+impl TryFrom<u8> for ProcessingMode {
+    type Error = ParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match isize::from(value) {
+            PROCESSING_MODE_REGULAR => Ok(ProcessingMode::Regular),
+            PROCESSING_MODE_SANDBOX => Ok(ProcessingMode::Sandbox),
+            _ => Err(ParseError::new("ProcessingMode", value.to_string())),
+        }
+    }
+}
+
 impl ProcessingMode {
+    /// Panics on an unknown value; use `ProcessingMode::try_from` for DB values.
     pub fn new(value: isize) -> ProcessingMode {
-        match value {
-            PROCESSING_MODE_REGULAR => ProcessingMode::Regular,
-            PROCESSING_MODE_SANDBOX => ProcessingMode::Sandbox,
-            _ => panic!("Unexpected ProcessingMode value"),
-        }
+        u8::try_from(value)
+            .map_err(|_| ParseError::new("ProcessingMode", value.to_string()))
+            .and_then(ProcessingMode::try_from)
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
@@ -119,6 +184,97 @@ impl AssignedProcess {
     }
 }
 
+/// Response body of the dispatcher `GET /process/{process_id}`.
+/// Empty optional fields are serialized as `null`, never omitted.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProcessStatus {
+    pub id: String,
+    pub source_id: u32,
+    pub state: DispatchState,
+    #[serde(rename = "mode")]
+    pub r#mode: ProcessingMode,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub created_at: DateTime<Utc>,
+    pub supervisor_id: Option<String>,
+    pub failure_reason: Option<String>,
+    /// Process class of `mode` (`PROCESS_CLASSES`), `default` if not configured.
+    #[serde(default = "default_process_class")]
+    pub class: String,
+}
+
+fn default_process_class() -> String {
+    DEFAULT_PROCESS_CLASS.to_string()
+}
+
+impl ProcessStatus {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: String,
+        source_id: u32,
+        state: DispatchState,
+        r#mode: ProcessingMode,
+        created_at: DateTime<Utc>,
+        supervisor_id: Option<String>,
+        failure_reason: Option<String>,
+        class: String,
+    ) -> Self {
+        ProcessStatus {
+            id,
+            source_id,
+            state,
+            r#mode,
+            created_at,
+            supervisor_id,
+            failure_reason,
+            class,
+        }
+    }
+}
+
+/// Response body of the dispatcher `GET /info`: build and DB versions of the
+/// running instance, to correlate behavior with a deploy.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DispatcherInfo {
+    pub version: String,
+    pub db_version: String,
+    /// Latest applied `db/migrations/` version, `null` if never migrated by sqlx.
+    pub migration_version: Option<i64>,
+}
+
+impl DispatcherInfo {
+    pub fn new(version: String, db_version: String, migration_version: Option<i64>) -> Self {
+        DispatcherInfo {
+            version,
+            db_version,
+            migration_version,
+        }
+    }
+}
+
+/// Response body of the dispatcher `GET /process/{process_id}/position`: 0-based
+/// rank among the assignable processes of the source and of all sources.
+/// Both are `null` when the process is not waiting (assigned or finished).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QueuePosition {
+    pub process_id: String,
+    pub source_position: Option<u64>,
+    pub global_position: Option<u64>,
+}
+
+impl QueuePosition {
+    pub fn new(
+        process_id: String,
+        source_position: Option<u64>,
+        global_position: Option<u64>,
+    ) -> Self {
+        QueuePosition {
+            process_id,
+            source_position,
+            global_position,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ProcessFinishReport {
     pub process_id: String,
@@ -130,3 +286,116 @@ impl ProcessFinishReport {
         ProcessFinishReport { process_id, result }
     }
 }
+
+/// Body of `POST /sources/{source_id}/processes`; `mode` is `regular` (default)
+/// or `sandbox`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct CreateProcessRequest {
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+/// Body of `POST /processes/{uuid}/state`; `state` is a lowercase finished
+/// state (`completed` or `failed`).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProcessStateUpdate {
+    pub state: String,
+}
+
+impl ProcessStateUpdate {
+    pub fn new(state: DispatchState) -> Self {
+        ProcessStateUpdate {
+            state: state.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process_status(
+        supervisor_id: Option<String>,
+        failure_reason: Option<String>,
+    ) -> ProcessStatus {
+        ProcessStatus::new(
+            "00000000-0000-0000-0000-000000000000".to_string(),
+            42,
+            DispatchState::Failed,
+            ProcessingMode::Regular,
+            DateTime::from_timestamp_millis(1_728_825_203_000).unwrap(),
+            supervisor_id,
+            failure_reason,
+            "default".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_empty_optionals_are_serialized_as_null() {
+        let json = serde_json::to_value(process_status(None, None)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "id": "00000000-0000-0000-0000-000000000000",
+                "source_id": 42,
+                "state": "Failed",
+                "mode": "Regular",
+                "created_at": 1_728_825_203_000_i64,
+                "supervisor_id": null,
+                "failure_reason": null,
+                "class": "default",
+            })
+        );
+    }
+
+    #[test]
+    fn test_set_optionals_are_serialized() {
+        let json = serde_json::to_value(process_status(
+            Some("11111111-1111-1111-1111-111111111111".to_string()),
+            Some("expired (too old)".to_string()),
+        ))
+        .unwrap();
+
+        assert_eq!(json["supervisor_id"], "11111111-1111-1111-1111-111111111111");
+        assert_eq!(json["failure_reason"], "expired (too old)");
+    }
+
+    #[test]
+    fn test_missing_optionals_are_deserialized_as_defaults() {
+        let status: ProcessStatus = serde_json::from_value(serde_json::json!({
+            "id": "00000000-0000-0000-0000-000000000000",
+            "source_id": 42,
+            "state": "Created",
+            "mode": "Regular",
+            "created_at": 1_728_825_203_000_i64,
+        }))
+        .unwrap();
+
+        assert_eq!(status.supervisor_id, None);
+        assert_eq!(status.failure_reason, None);
+        assert_eq!(status.class, DEFAULT_PROCESS_CLASS);
+    }
+
+    #[test]
+    fn test_known_db_values_are_parsed() {
+        assert_eq!(DispatchState::try_from("pending"), Ok(DispatchState::Pending));
+        assert_eq!(DispatchState::try_from("failed"), Ok(DispatchState::Failed));
+        assert_eq!(ProcessingMode::try_from(1), Ok(ProcessingMode::Regular));
+        assert_eq!(ProcessingMode::try_from(2), Ok(ProcessingMode::Sandbox));
+    }
+
+    #[test]
+    fn test_unknown_db_values_are_errors() {
+        let e = DispatchState::try_from("paused").unwrap_err();
+        assert_eq!(e.to_string(), "unexpected DispatchState value 'paused'");
+        //the PascalCase JSON form is not a DB value
+        assert_eq!(
+            DispatchState::try_from("Pending").unwrap_err(),
+            ParseError::new("DispatchState", "Pending")
+        );
+
+        let e = ProcessingMode::try_from(3).unwrap_err();
+        assert_eq!(e.to_string(), "unexpected ProcessingMode value '3'");
+    }
+}